@@ -18,6 +18,7 @@ let
         src = final.nix-gitignore.gitignoreSource [] ./.;
 
         buildInputs = with final; [
+          dbus
           glib
           gtk3
           openssl
@@ -68,6 +69,7 @@ let
         LIBCLANG_PATH = "${final.llvmPackages.libclang}/lib";
 
         buildInputs = with final; [
+          dbus
           openssl
 
           # GTK libraries
@@ -0,0 +1,122 @@
+// This module defines the clocks break-time uses for timing breaks.
+//
+// The wall clock (`SystemTime`) can jump forwards or backwards at any time, for instance when NTP
+// corrects it or the user changes the time zone by hand.  Timing a break with it could end the
+// break right away or stretch it for hours, so all timing is done with monotonic clocks instead:
+//
+// - `BootInstant` uses `CLOCK_BOOTTIME`, which keeps counting while the computer is suspended.
+//   This is used when time spent suspended should count, like for the length of a break or for
+//   how long the user has been idle.
+// - `std::time::Instant` uses `CLOCK_MONOTONIC`, which stops while the computer is suspended.
+//   This is used when time spent suspended should not count, like for timeouts.
+//
+// The wall clock is still used for things that need to make sense across reboots, like the saved
+// scheduler state and the break history.
+//
+// - https://man7.org/linux/man-pages/man2/clock_gettime.2.html
+
+#![allow(unsafe_code)]
+
+use std::ops::{Add, Sub};
+use std::time::Duration;
+
+use crate::prelude::*;
+
+/// A point in time on the `CLOCK_BOOTTIME` clock.
+///
+/// This works like `std::time::Instant`, except that it includes the time the
+/// computer was suspended.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct BootInstant {
+    since_boot: Duration,
+}
+
+impl BootInstant {
+    pub fn now() -> Self {
+        Self {
+            since_boot: clock_gettime(libc::CLOCK_BOOTTIME)
+                // CLOCK_BOOTTIME was only added in Linux 2.6.39, so fall back
+                // to a clock that doesn't include suspend.
+                .or_else(|_| clock_gettime(libc::CLOCK_MONOTONIC))
+                .expect("CLOCK_MONOTONIC should always be available"),
+        }
+    }
+
+    /// The amount of time from `earlier` to `self`, or zero if `earlier` is
+    /// later than `self`.
+    pub fn duration_since(self, earlier: Self) -> Duration {
+        self.since_boot
+            .checked_sub(earlier.since_boot)
+            .unwrap_or_default()
+    }
+
+    pub fn elapsed(self) -> Duration {
+        Self::now().duration_since(self)
+    }
+
+    pub fn checked_add(self, duration: Duration) -> Option<Self> {
+        self.since_boot
+            .checked_add(duration)
+            .map(|since_boot| Self { since_boot })
+    }
+
+    pub fn checked_sub(self, duration: Duration) -> Option<Self> {
+        self.since_boot
+            .checked_sub(duration)
+            .map(|since_boot| Self { since_boot })
+    }
+}
+
+impl Add<Duration> for BootInstant {
+    type Output = Self;
+
+    fn add(self, duration: Duration) -> Self {
+        self.checked_add(duration)
+            .expect("overflow when adding duration to instant")
+    }
+}
+
+impl Sub<Self> for BootInstant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Self) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+fn clock_gettime(clock_id: libc::clockid_t) -> std::io::Result<Duration> {
+    let mut timespec = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    let res = unsafe { libc::clock_gettime(clock_id, &mut timespec) };
+    if res != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let secs = u64::try_from(timespec.tv_sec).unwrap_or_default();
+    let nanos = u32::try_from(timespec.tv_nsec).unwrap_or_default();
+    Ok(Duration::new(secs, nanos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boot_instant() {
+        let start = BootInstant::now();
+        std::thread::sleep(Duration::from_millis(20));
+        let end = BootInstant::now();
+
+        assert!(end > start);
+        assert!(end - start >= Duration::from_millis(20));
+        assert!(start.elapsed() >= end - start);
+
+        // Going backwards saturates at zero instead of panicking.
+        assert_eq!(start.duration_since(end), Duration::from_secs(0));
+
+        let later = start + Duration::from_secs(60);
+        assert_eq!(later - start, Duration::from_secs(60));
+        assert_eq!(later.checked_sub(Duration::from_secs(60)), Some(start));
+    }
+}
@@ -1,47 +1,97 @@
 mod default;
+mod watcher;
 
 use std::default::Default;
-use std::path::PathBuf;
+use std::fmt;
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 
 use crate::opts::Opts;
+use crate::scheduler::plugins::{
+    self, capture_devices, command, fullscreen, google_calendar, ics,
+    processes, window_titles,
+};
 
 use default::DEFAULT_CONFIG_SETTINGS;
+pub use watcher::watch;
 
-#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
-#[serde(transparent)]
-pub struct PluginSettings(pub toml::value::Table);
+/// The settings for all the plugins.  Each plugin has its own section in the
+/// config file, like `[plugin.google_calendar]`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginSettings {
+    /// The policy for plugins that don't have their own.
+    #[serde(default)]
+    pub default_policy: plugins::PolicySettings,
+    #[serde(default)]
+    pub google_calendar: google_calendar::Settings,
+    #[serde(default)]
+    pub ics: ics::Settings,
+    #[serde(default)]
+    pub x11_window_title_checker: window_titles::Settings,
+    #[serde(default)]
+    pub processes: processes::Settings,
+    #[serde(default)]
+    pub capture_devices: capture_devices::Settings,
+    #[serde(default)]
+    pub fullscreen: fullscreen::Settings,
+    /// Each `[[plugin.command]]` section is a separate plugin.
+    #[serde(default)]
+    pub command: Vec<command::Settings>,
+}
 
-impl Default for PluginSettings {
-    fn default() -> Self {
-        let mut google_cal: toml::value::Table = toml::map::Map::new();
-        let google_cal_accounts: Vec<String> = vec![];
-        let google_cal_accounts_val = toml::Value::try_from(google_cal_accounts)
-            .expect(
-                "Could not decode google_cal_accounts as toml Value, even though we should be able to."
-            );
-        google_cal.insert(String::from("accounts"), google_cal_accounts_val);
-
-        let x11_window_title_checker: toml::value::Table =
-            toml::map::Map::new();
-
-        let mut plugin_settings_table: toml::value::Table =
-            toml::map::Map::new();
-        plugin_settings_table.insert(
-            String::from("google_calendar"),
-            toml::Value::Table(google_cal),
-        );
-        plugin_settings_table.insert(
-            String::from("x11_window_title_checker"),
-            toml::Value::Table(x11_window_title_checker),
-        );
-
-        Self(plugin_settings_table)
+impl PluginSettings {
+    /// Check the settings for problems that can't be caught when parsing the
+    /// config file.  Returns a message describing the first problem found.
+    pub fn validate(&self) -> Result<(), String> {
+        self.x11_window_title_checker.validate().map_err(|err| {
+            format!("plugin.x11_window_title_checker: {}", err)
+        })?;
+        self.fullscreen
+            .validate()
+            .map_err(|err| format!("plugin.fullscreen: {}", err))?;
+        self.processes
+            .validate()
+            .map_err(|err| format!("plugin.processes: {}", err))?;
+        for (i, command_settings) in self.command.iter().enumerate() {
+            command_settings
+                .validate()
+                .map_err(|err| format!("plugin.command[{}]: {}", i, err))?;
+        }
+        Ok(())
     }
 }
 
+/// How time spent idle counts towards the next break.
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdleCreditMode {
+    /// Being idle for `idle_detection_seconds` counts as a break, and
+    /// restarts the countdowns.  Shorter idle times count for nothing.
+    Reset,
+    /// Being idle for `idle_detection_seconds` still counts as a break, but
+    /// shorter idle times also push the next break back by
+    /// `idle_credit_percent` of the idle time.
+    Proportional,
+}
+
+/// A kind of break, like a short break to rest your eyes, or a long break to
+/// walk around.  Each kind of break has its own countdown.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BreakTier {
+    pub name: String,
+    pub break_duration_seconds: u32,
+    pub seconds_between_breaks: u32,
+    #[serde(default = "default_clicks_to_end_break_early")]
+    pub clicks_to_end_break_early: u32,
+    #[serde(default = "default_break_message")]
+    pub message: String,
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct Settings {
     #[serde(default = "default_break_duration_seconds")]
     pub break_duration_seconds: u32,
@@ -53,7 +103,19 @@ pub struct Settings {
     pub idle_detection_enabled: bool,
     #[serde(default = "default_idle_detection_seconds")]
     pub idle_detection_seconds: u32,
-    #[serde(rename = "plugin")]
+    #[serde(default = "default_idle_credit_mode")]
+    pub idle_credit_mode: IdleCreditMode,
+    #[serde(default = "default_idle_credit_percent")]
+    pub idle_credit_percent: u32,
+    #[serde(default = "default_postpone_seconds")]
+    pub postpone_seconds: u32,
+    #[serde(default = "default_max_postpones_in_a_row")]
+    pub max_postpones_in_a_row: u32,
+    #[serde(default = "default_recheck_seconds_when_blocked")]
+    pub recheck_seconds_when_blocked: u32,
+    #[serde(default, rename = "break_tier")]
+    pub break_tiers: Vec<BreakTier>,
+    #[serde(default, rename = "plugin")]
     pub all_plugin_settings: PluginSettings,
 }
 
@@ -69,6 +131,10 @@ const fn default_clicks_to_end_break_early() -> u32 {
     400
 }
 
+fn default_break_message() -> String {
+    String::from("Time to take a break")
+}
+
 const fn default_idle_detection_enabled() -> bool {
     true
 }
@@ -77,6 +143,26 @@ const fn default_idle_detection_seconds() -> u32 {
     480
 }
 
+const fn default_idle_credit_mode() -> IdleCreditMode {
+    IdleCreditMode::Reset
+}
+
+const fn default_idle_credit_percent() -> u32 {
+    100
+}
+
+const fn default_postpone_seconds() -> u32 {
+    60 * 5
+}
+
+const fn default_max_postpones_in_a_row() -> u32 {
+    2
+}
+
+const fn default_recheck_seconds_when_blocked() -> u32 {
+    60
+}
+
 impl Default for Settings {
     fn default() -> Self {
         Self {
@@ -86,10 +172,132 @@ impl Default for Settings {
             all_plugin_settings: PluginSettings::default(),
             idle_detection_enabled: default_idle_detection_enabled(),
             idle_detection_seconds: default_idle_detection_seconds(),
+            idle_credit_mode: default_idle_credit_mode(),
+            idle_credit_percent: default_idle_credit_percent(),
+            postpone_seconds: default_postpone_seconds(),
+            max_postpones_in_a_row: default_max_postpones_in_a_row(),
+            recheck_seconds_when_blocked: default_recheck_seconds_when_blocked(
+            ),
+            break_tiers: vec![],
+        }
+    }
+}
+
+impl Settings {
+    /// Return all the kinds of breaks that should be scheduled.
+    ///
+    /// If there are no `[[break_tier]]` sections in the config file, then
+    /// there is just a single kind of break, which is configured with
+    /// `break_duration_seconds`, `seconds_between_breaks`, and
+    /// `clicks_to_end_break_early`.
+    pub fn break_tiers(&self) -> Vec<BreakTier> {
+        if self.break_tiers.is_empty() {
+            vec![BreakTier {
+                name: String::from("break"),
+                break_duration_seconds: self.break_duration_seconds,
+                seconds_between_breaks: self.seconds_between_breaks,
+                clicks_to_end_break_early: self.clicks_to_end_break_early,
+                message: default_break_message(),
+            }]
+        } else {
+            self.break_tiers.clone()
+        }
+    }
+
+    /// Parse the contents of the config file at `file_path`, and check that
+    /// the plugin settings are valid.
+    pub fn parse(file_path: &Path, config_file: &str) -> Result<Self, Error> {
+        let settings: Self = toml::from_str(config_file)
+            .map_err(|err| Error::Parse(file_path.to_path_buf(), err))?;
+        settings
+            .all_plugin_settings
+            .validate()
+            .map_err(|err| Error::Invalid(file_path.to_path_buf(), err))?;
+        Ok(settings)
+    }
+
+    /// Read and parse the config file at `file_path`.
+    pub fn read(file_path: &Path) -> Result<Self, Error> {
+        let config_file = std::fs::read_to_string(file_path)
+            .map_err(|err| Error::ReadFile(file_path.to_path_buf(), err))?;
+        Self::parse(file_path, &config_file)
+    }
+
+    /// Look for combinations of settings that are allowed, but probably not
+    /// what the user wants.  Returns a message for each one found.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = vec![];
+        for break_tier in self.break_tiers() {
+            if break_tier.break_duration_seconds == 0 {
+                warnings.push(format!(
+                    "break_duration_seconds is 0 for break tier \"{}\", so its breaks end as soon as they start",
+                    break_tier.name
+                ));
+            }
+            if self.idle_detection_enabled
+                && self.idle_detection_seconds
+                    > break_tier.seconds_between_breaks
+            {
+                warnings.push(format!(
+                    "idle_detection_seconds ({}) is greater than seconds_between_breaks ({}) for break tier \"{}\", so a break will always start before you are detected as idle",
+                    self.idle_detection_seconds,
+                    break_tier.seconds_between_breaks,
+                    break_tier.name
+                ));
+            }
+        }
+        if self.idle_credit_mode == IdleCreditMode::Proportional
+            && self.idle_credit_percent > 100
+        {
+            warnings.push(format!(
+                "idle_credit_percent ({}) is greater than 100, so being idle for a short time pushes the next break back by more than the time spent idle",
+                self.idle_credit_percent
+            ));
         }
+        warnings
     }
 }
 
+/// An error that stops the config from being loaded.
+#[derive(Debug)]
+pub enum Error {
+    /// The XDG base directories couldn't be figured out.
+    Xdg(xdg::BaseDirectoriesError),
+    CreateDir(PathBuf, std::io::Error),
+    ReadFile(PathBuf, std::io::Error),
+    WriteDefaultFile(PathBuf, std::io::Error),
+    /// The config file is not valid TOML, or some setting has the wrong type
+    /// or an unknown key.  The toml error says which key and line.
+    Parse(PathBuf, toml::de::Error),
+    /// The config file parsed, but some setting doesn't make sense.
+    Invalid(PathBuf, String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Xdg(err) => {
+                write!(f, "could not find the XDG base directories: {}", err)
+            }
+            Self::CreateDir(path, err) => {
+                write!(f, "could not create directory {:?}: {}", path, err)
+            }
+            Self::ReadFile(path, err) => {
+                write!(f, "could not read config file {:?}: {}", path, err)
+            }
+            Self::WriteDefaultFile(path, err) => write!(
+                f,
+                "could not write a new config file at {:?}: {}",
+                path, err
+            ),
+            Self::Parse(path, err) => write!(f, "{:?}: {}", path, err),
+            Self::Invalid(path, msg) => write!(f, "{:?}: {}", path, msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 #[derive(Clone, Debug)]
 pub struct Config {
     pub file_path: PathBuf,
@@ -97,72 +305,57 @@ pub struct Config {
     pub settings: Settings,
 }
 
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+fn xdg_base_dir() -> Result<xdg::BaseDirectories, Error> {
+    xdg::BaseDirectories::with_prefix("break-time").map_err(Error::Xdg)
+}
+
+fn create_dir(dir: &Path) -> Result<(), Error> {
+    std::fs::create_dir_all(dir)
+        .map_err(|err| Error::CreateDir(dir.to_path_buf(), err))
+}
+
 impl Config {
-    // TODO: Change some of the panics in this function to returning errors.
-    pub fn load(opts: &Opts) -> Result<Self, ()> {
-        let config_file_name = "config.toml";
-        let config_file_path = match &opts.conf_dir {
+    /// The path to the config file.  The directory holding it is created if
+    /// it doesn't exist.
+    pub fn file_path(opts: &Opts) -> Result<PathBuf, Error> {
+        match &opts.conf_dir {
             Some(conf_dir) => {
-                std::fs::create_dir_all(&conf_dir).map_err(|_io_err| ())?;
-                conf_dir.join(config_file_name)
+                create_dir(conf_dir)?;
+                Ok(conf_dir.join(CONFIG_FILE_NAME))
             }
             None => {
-                let xdg_base_dir =
-                    xdg::BaseDirectories::with_prefix("break-time")
-                        .map_err(|_xdg_base_dir_err| ())?;
-                xdg_base_dir
-                    .place_config_file(config_file_name)
-                    .map_err(|_io_err| ())?
+                let xdg_base_dir = xdg_base_dir()?;
+                xdg_base_dir.place_config_file(CONFIG_FILE_NAME).map_err(
+                    |err| Error::CreateDir(xdg_base_dir.get_config_home(), err),
+                )
             }
-        };
+        }
+    }
+
+    pub fn load(opts: &Opts) -> Result<Self, Error> {
+        let config_file_path = Self::file_path(opts)?;
 
         let cache_dir = match &opts.cache_dir {
             Some(cache_dir) => cache_dir.clone(),
-            None => {
-                let xdg_base_dir =
-                    xdg::BaseDirectories::with_prefix("break-time")
-                        .map_err(|_xdg_base_dir_err| ())?;
-                xdg_base_dir.get_cache_home()
-            }
+            None => xdg_base_dir()?.get_cache_home(),
         };
-        std::fs::create_dir_all(&cache_dir).map_err(|_io_err| ())?;
-
-        // Try reading the config file to see whether it exists or not.
-        let res_config_file = std::fs::read_to_string(&config_file_path);
-        let settings = match res_config_file {
-            // TODO: I should probably check here the reason we are getting an
-            // error.  If there is a bad permission on the file, then I
-            // should probably just error out fast instead of trying to
-            // create a new config file.
-            Err(_) => {
-                // If we couldn't read the config file, then create a new one
-                // from the default.
-                let write_res =
-                    std::fs::write(&config_file_path, DEFAULT_CONFIG_SETTINGS);
-                match write_res {
-                    Ok(()) => (),
-                    Err(err) =>
-                        panic!(
-                            "Couldn't write a new config file at {:?} because of the following error: {}",
-                            config_file_path,
-                            err
-                        ),
-                }
+        create_dir(&cache_dir)?;
+
+        let settings = match Settings::read(&config_file_path) {
+            Err(Error::ReadFile(_, err))
+                if err.kind() == std::io::ErrorKind::NotFound =>
+            {
+                // If there is no config file yet, then create a new one from
+                // the default.
+                std::fs::write(&config_file_path, DEFAULT_CONFIG_SETTINGS)
+                    .map_err(|err| {
+                        Error::WriteDefaultFile(config_file_path.clone(), err)
+                    })?;
                 Settings::default()
             }
-            Ok(config_file) => {
-                let res_settings = toml::from_str(&config_file);
-                match res_settings {
-                    Err(err) => {
-                        panic!(
-                            "Can't parse config file at {:?} because of the following error: {}",
-                            config_file_path,
-                            err
-                        )
-                    }
-                    Ok(settings) => settings,
-                }
-            }
+            res_settings => res_settings?,
         };
 
         let config = Self {
@@ -187,4 +380,52 @@ mod tests {
 
         assert_eq!(settings_from_default_instance, settings_from_default_const);
     }
+
+    #[test]
+    fn test_misspelled_key_is_an_error() {
+        let err = toml::from_str::<Settings>("idle_detection_second = 60")
+            .unwrap_err();
+
+        assert!(err.to_string().starts_with(
+            "unknown field `idle_detection_second`, expected one of"
+        ));
+    }
+
+    #[test]
+    fn test_parse_error_has_line_and_key() {
+        let err = Settings::parse(
+            Path::new("config.toml"),
+            "break_duration_seconds = 600\nseconds_between_breaks = \"an hour\"\n",
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.starts_with("\"config.toml\": "));
+        assert!(err.contains("for key `seconds_between_breaks` at line 2"));
+    }
+
+    #[test]
+    fn test_warnings() {
+        assert!(Settings::default().warnings().is_empty());
+
+        let settings = Settings {
+            break_duration_seconds: 0,
+            seconds_between_breaks: 300,
+            idle_detection_seconds: 600,
+            ..Settings::default()
+        };
+        assert_eq!(settings.warnings().len(), 2);
+
+        let settings = Settings {
+            idle_detection_enabled: false,
+            ..settings
+        };
+        assert_eq!(settings.warnings().len(), 1);
+
+        let settings = Settings {
+            idle_credit_mode: IdleCreditMode::Proportional,
+            idle_credit_percent: 150,
+            ..Settings::default()
+        };
+        assert_eq!(settings.warnings().len(), 1);
+    }
 }
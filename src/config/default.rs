@@ -25,10 +25,179 @@ pub const DEFAULT_CONFIG_SETTINGS: &str = indoc!(
     # another seconds_between_breaks until starting another break.
     idle_detection_seconds = 480 # 8 minutes
 
+    # How being idle for less than `idle_detection_seconds` counts towards the next break.
+    #
+    # With \"reset\" (the default), it counts for nothing.  With \"proportional\", being idle
+    # for a few minutes pushes the next break back by `idle_credit_percent` percent of the
+    # time spent idle.  Either way, being idle for `idle_detection_seconds` counts as a
+    # full break.
+    idle_credit_mode = \"reset\"
+    idle_credit_percent = 100
+
+    # The number of seconds to push a break back when you postpone it.  A break can be
+    # postponed from the break screen, or from the systray icon right before a break
+    # starts.
+    postpone_seconds = 300 # 5 minutes
+
+    # The number of times in a row a break can be postponed.  After postponing this many
+    # times, the next break can't be postponed, and you will have to take it.  Set this
+    # to 0 to disable postponing breaks.
+    max_postpones_in_a_row = 2
+
+    # When a plugin stops a break from starting (for instance, because you are in a video
+    # call), the plugins are asked again after this many seconds.  The break starts soon
+    # after the plugins stop blocking it.  Set this to 0 to wait a full
+    # `seconds_between_breaks` instead.
+    recheck_seconds_when_blocked = 60
+
+    # Instead of a single kind of break, you can have multiple kinds of breaks, each with
+    # their own countdown.  For instance, you can have a short break every 20 minutes to
+    # rest your eyes, and a long break every hour to walk around.
+    #
+    # If any `[[break_tier]]` sections are given, then `break_duration_seconds`,
+    # `seconds_between_breaks`, and `clicks_to_end_break_early` above are ignored.
+    #
+    # When two kinds of breaks are due at the same time, the longer break is taken.  Taking
+    # a break also resets the countdown for all the shorter kinds of breaks.
+    #
+    # [[break_tier]]
+    # name = \"eye break\"
+    # break_duration_seconds = 20
+    # seconds_between_breaks = 1200 # 20 minutes
+    # clicks_to_end_break_early = 20
+    # message = \"Look at something far away\"
+    #
+    # [[break_tier]]
+    # name = \"long break\"
+    # break_duration_seconds = 600 # 10 minutes
+    # seconds_between_breaks = 3600 # 60 minutes
+    # message = \"Time to get up and walk around\"
+
     [plugin.google_calendar]
     # A list of strings, one for each Google account you want to authenticate with.
     accounts = []
 
+    [plugin.ics]
+    # iCalendar (.ics) files to check for events, or directories that are searched for
+    # .ics files, like the ones vdirsyncer writes.  For example:
+    # paths = [\"~/.calendars/work\", \"~/Downloads/holidays.ics\"]
+    paths = []
+    # Your email addresses, so that events you have declined don't prevent a break.
+    emails = []
+
+    # All the plugins are asked whether a break can happen at the same time.  A plugin that
+    # doesn't answer within `timeout_seconds` is given up on, and `on_timeout` decides what
+    # happens: \"block_break\" delays the break, \"allow_break\" lets the break happen, and
+    # \"retry\" asks the plugin once more before delaying the break.  Each plugin can
+    # override these in its own `policy` table, like `[plugin.google_calendar.policy]`.
+    #
+    # `on_error` decides what happens when a plugin fails with an error: \"block_break\",
+    # \"allow_break\", or \"ignore_plugin\" to disable the plugin.  A plugin that fails
+    # `max_consecutive_failures` times in a row is also disabled (0 means never).
+    #
+    # Once a plugin has delayed a break for `max_deferral_seconds`, it can't delay it any
+    # longer, and the break starts anyway (0 means there is no limit).
+    #
+    # [plugin.default_policy]
+    # timeout_seconds = 30
+    # on_timeout = \"block_break\"
+    # on_error = \"block_break\"
+    # max_consecutive_failures = 5
+    # max_deferral_seconds = 0
+
     [plugin.x11_window_title_checker]
+    # Whether or not to use the built-in rules for detecting video calls.  These detect
+    # BigBlueButton, Google Meet, Jitsi, and Slack calls in a web browser, as well as
+    # Skype and Zoom.
+    use_default_rules = true
+
+    # Which windows to check the rules against:
+    #
+    # - \"top_level\": the direct children of the root window.  Window managers that put
+    #   windows inside frames hide them from this.
+    # - \"client_list\": every window in _NET_CLIENT_LIST, including minimized ones.
+    # - \"active\": only the window that has focus.
+    # - \"visible\": the windows in _NET_CLIENT_LIST that aren't minimized or on a
+    #   different workspace.
+    windows = \"top_level\"
+
+    # Additional rules for windows that should prevent a break from occurring.  A window
+    # matches a rule if it matches all of the conditions given in the rule.  The
+    # following conditions can be used:
+    #
+    # - `class`: the class part of WM_CLASS (like \"Firefox\")
+    # - `class_name`: the instance name part of WM_CLASS (like \"Navigator\")
+    # - `net_wm_name`: the _NET_WM_NAME window title
+    # - `wm_name`: the WM_NAME window title
+    # - `browser`: set to true to only match windows from a known web browser
+    #
+    # Each of the text conditions can be matched with `exact`, `prefix`, `contains`, or
+    # `regex`.  You can use `xprop` to find the values for a given window.
+    #
+    # [[plugin.x11_window_title_checker.rules]]
+    # name = \"Microsoft Teams meeting\"
+    # class = { exact = \"Microsoft Teams - Preview\" }
+    # net_wm_name = { regex = \"^Meeting .* \\\\| Microsoft Teams$\" }
+
+    [plugin.fullscreen]
+    # Prevent breaks while a window is fullscreen, like a presentation, a game, or a
+    # video.
+    enabled = false
+    # Which windows to check, which can be any of the values for
+    # plugin.x11_window_title_checker.windows above.
+    windows = \"active\"
+    # Fullscreen windows with a class or instance name (from WM_CLASS) matching one of
+    # these never prevent a break.  These can be matched with `exact`, `prefix`,
+    # `contains`, or `regex`.
+    allow_classes = []
+    # If this isn't empty, only fullscreen windows with a class or instance name
+    # matching one of these prevent a break.
+    deny_classes = []
+    # For example:
+    # allow_classes = [{ exact = \"Alacritty\" }, { exact = \"Firefox\" }]
+
+    [plugin.capture_devices]
+    # Prevent breaks while a camera or a microphone is being used, like during a call.
+    enabled = false
+    # Prevent breaks while a camera (/dev/video*) is being used.
+    camera = true
+    # Prevent breaks while a microphone (an ALSA capture device) is being used.
+    microphone = true
+
+    # Rules for processes that should prevent a break from occurring while they are
+    # running.  A process matches a rule if it matches all of the conditions given in the
+    # rule.  The following conditions can be used:
+    #
+    # - `comm`: the name of the program from /proc/PID/comm (like \"zoom\")
+    # - `cmdline`: the whole command line, with the arguments separated by spaces
+    # - `args`: a list of conditions that each have to match one of the arguments
+    # - `user`: only match processes owned by this user name or user ID
+    #
+    # The text conditions can be matched with `exact`, `prefix`, `contains`, or `regex`,
+    # just like the window title rules.  You can use `ps -eo comm,user,args` to find the
+    # values for a given process.
+    #
+    # [[plugin.processes.rules]]
+    # name = \"OBS\"
+    # comm = { exact = \"obs\" }
+    #
+    # [[plugin.processes.rules]]
+    # name = \"screen recording with ffmpeg\"
+    # comm = { exact = \"ffmpeg\" }
+    # args = [{ exact = \"x11grab\" }]
+
+    # Commands that decide whether or not a break can happen.  Each command is run with
+    # `sh -c` right before a break.  If it exits with 0 the break can happen, and if it
+    # exits with 1 the break is delayed.  Any other exit status is treated as an error.
+    #
+    # [[plugin.command]]
+    # name = \"in a meeting\"
+    # cmd = \"~/bin/in-meeting\"
+    # # The command is killed if it takes longer than this.
+    # timeout_seconds = 10
+    # # The directory to run the command in.
+    # working_dir = \"~\"
+    # # Extra environment variables for the command.
+    # env = { MEETING_CALENDAR = \"work\" }
     "
 );
@@ -0,0 +1,103 @@
+// This module watches the config file with inotify, so that changes to the config file can be
+// picked up without restarting break-time.
+//
+// The directory holding the config file is watched instead of the file itself, because many
+// editors save a file by writing out a new file and renaming it over the old one.  This would
+// leave a watch on the file itself pointing at the old, deleted file.
+
+use inotify::{Inotify, WatchMask};
+use std::path::PathBuf;
+
+use super::{Error, Settings};
+
+/// Start watching the config file in a separate thread.
+///
+/// Every time the config file is written to, it is read and parsed again, and
+/// `on_change` is called with the result.
+pub fn watch<F>(file_path: PathBuf, on_change: F) -> Result<(), ()>
+where
+    F: Fn(Result<Settings, Error>) + Send + 'static,
+{
+    let (dir, file_name) = match (file_path.parent(), file_path.file_name()) {
+        (Some(dir), Some(file_name)) => (dir, file_name.to_owned()),
+        _ => {
+            println!(
+                "Could not figure out the directory of the config file {:?}",
+                file_path
+            );
+            return Err(());
+        }
+    };
+
+    let mut inotify = Inotify::init().map_err(|err| {
+        println!("Could not initialize inotify: {}", err);
+    })?;
+    inotify
+        .add_watch(dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)
+        .map_err(|err| {
+            println!("Could not watch {:?} for changes: {}", dir, err);
+        })?;
+
+    std::thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        loop {
+            let mut events = match inotify.read_events_blocking(&mut buffer) {
+                Err(err) => {
+                    println!(
+                        "Could not read inotify events, so no longer watching the config file: {}",
+                        err
+                    );
+                    return;
+                }
+                Ok(events) => events,
+            };
+
+            if events.any(|event| event.name == Some(file_name.as_os_str())) {
+                println!("Config file {:?} has changed", file_path);
+                on_change(Settings::read(&file_path));
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    #[test]
+    fn test_watch() {
+        let dir = std::env::temp_dir()
+            .join(format!("break-time-test-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file_path = dir.join("config.toml");
+        std::fs::write(&file_path, "").unwrap();
+
+        let (sender, receiver) = channel();
+        watch(file_path.clone(), move |res_settings| {
+            sender.send(res_settings).unwrap();
+        })
+        .unwrap();
+
+        std::fs::write(&file_path, "break_duration_seconds = 30").unwrap();
+        let settings = receiver
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap()
+            .unwrap();
+        assert_eq!(settings.break_duration_seconds, 30);
+
+        // Other files in the same directory are ignored.
+        std::fs::write(dir.join("other.toml"), "").unwrap();
+        std::fs::write(&file_path, "break_duration_seconds = \"thirty\"")
+            .unwrap();
+        assert!(receiver
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap()
+            .is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,346 @@
+// This module defines a control socket for break-time.
+//
+// When break-time is running, it listens on a Unix domain socket in $XDG_RUNTIME_DIR.  Other
+// programs (like `break-time ctl`) can connect to this socket to control the running break-time.
+//
+// The protocol is line-based.  The client sends a single line with the name of a command, like
+// `pause`.  break-time replies with a single line, either `ok <message>` if the command
+// succeeded, or `error <message>` if it failed.  Then the connection is closed.
+//
+// The `follow` commands are the exception.  break-time keeps the connection open and sends an
+// `ok <status>` line every time the status changes.
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Sender};
+use std::time::Duration;
+
+use super::status::Status;
+use super::Msg;
+
+const SOCKET_FILE_NAME: &str = "ctl.sock";
+
+/// The amount of time to wait for the main thread to reply to a command.
+const REPLY_TIMEOUT_SECONDS: u64 = 5;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StatusFormat {
+    Text,
+    Json,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Command {
+    Pause,
+    Resume,
+    BreakNow,
+    Status(StatusFormat),
+    /// Get the status every time it changes.
+    Follow(StatusFormat),
+    Skip,
+}
+
+impl Command {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Pause => "pause",
+            Self::Resume => "resume",
+            Self::BreakNow => "break-now",
+            Self::Status(StatusFormat::Text) => "status",
+            Self::Status(StatusFormat::Json) => "status-json",
+            Self::Follow(StatusFormat::Text) => "follow",
+            Self::Follow(StatusFormat::Json) => "follow-json",
+            Self::Skip => "skip",
+        }
+    }
+
+    fn from_str(command: &str) -> Option<Self> {
+        match command {
+            "pause" => Some(Self::Pause),
+            "resume" => Some(Self::Resume),
+            "break-now" => Some(Self::BreakNow),
+            "status" => Some(Self::Status(StatusFormat::Text)),
+            "status-json" => Some(Self::Status(StatusFormat::Json)),
+            "follow" => Some(Self::Follow(StatusFormat::Text)),
+            "follow-json" => Some(Self::Follow(StatusFormat::Json)),
+            "skip" => Some(Self::Skip),
+            _ => None,
+        }
+    }
+
+    const fn is_follow(self) -> bool {
+        matches!(self, Self::Follow(_))
+    }
+}
+
+/// A command that has been received on the control socket, along with a
+/// way to reply to it.
+///
+/// The reply is either a message saying what happened, or an error message.
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub command: Command,
+    pub reply_sender: Sender<Result<String, String>>,
+}
+
+impl Request {
+    pub fn reply(&self, reply: Result<String, String>) {
+        // The client may have already gone away, in which case there is
+        // nobody to reply to.
+        let _ = self.reply_sender.send(reply);
+    }
+}
+
+/// The clients that have sent a `Follow` command, and are waiting for status
+/// updates.
+#[derive(Default)]
+pub struct Followers(Vec<Request>);
+
+impl Followers {
+    pub fn add(&mut self, request: Request) {
+        self.0.push(request);
+    }
+
+    /// Send the status to all followers.  Followers that have gone away are
+    /// forgotten about.
+    pub fn send_status(&mut self, status: &Status) {
+        self.0.retain(|request| {
+            let status_text = match request.command {
+                Command::Follow(StatusFormat::Json) => status.to_json(),
+                _ => status.to_text(),
+            };
+            request.reply_sender.send(Ok(status_text)).is_ok()
+        });
+    }
+}
+
+fn socket_path() -> Result<PathBuf, ()> {
+    let xdg_base_dir = xdg::BaseDirectories::with_prefix("break-time")
+        .map_err(|_xdg_base_dir_err| ())?;
+    xdg_base_dir
+        .place_runtime_file(SOCKET_FILE_NAME)
+        .map_err(|_io_err| ())
+}
+
+/// Start listening on the control socket in a separate thread.
+///
+/// Commands that are received are sent to the main thread as `Msg::Ctl`.
+pub fn listen(sender: glib::Sender<Msg>) -> Result<(), ()> {
+    let path = socket_path().map_err(|()| {
+        println!("Could not figure out the path for the control socket, is $XDG_RUNTIME_DIR set?");
+    })?;
+
+    // If there is a socket file left over from a previous run, check whether
+    // there is still a break-time listening on it before removing it.
+    if path.exists() {
+        if UnixStream::connect(&path).is_ok() {
+            println!(
+                "Another break-time is already listening on the control socket at {:?}",
+                path
+            );
+            return Err(());
+        }
+        std::fs::remove_file(&path).map_err(|err| {
+            println!(
+                "Could not remove old control socket at {:?}: {}",
+                path, err
+            );
+        })?;
+    }
+
+    let listener = UnixListener::bind(&path).map_err(|err| {
+        println!("Could not listen on control socket at {:?}: {}", path, err);
+    })?;
+    println!("Listening for commands on control socket at {:?}", path);
+
+    std::thread::spawn(move || {
+        for res_stream in listener.incoming() {
+            match res_stream {
+                Err(err) => {
+                    println!(
+                        "Error accepting connection on control socket: {}",
+                        err
+                    );
+                }
+                Ok(stream) => {
+                    // Each client gets its own thread, since followers keep
+                    // their connection open.
+                    let sender = sender.clone();
+                    std::thread::spawn(move || {
+                        if let Err(err) = handle_client(&sender, stream) {
+                            println!(
+                                "Error handling control socket client: {}",
+                                err
+                            );
+                        }
+                    });
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn handle_client(
+    sender: &glib::Sender<Msg>,
+    mut stream: UnixStream,
+) -> std::io::Result<()> {
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+
+    let command = match Command::from_str(line.trim()) {
+        None => {
+            return write_reply(
+                &mut stream,
+                Err(format!("unknown command: {}", line.trim())),
+            );
+        }
+        Some(command) => command,
+    };
+
+    let (reply_sender, reply_receiver) = channel();
+    sender
+        .send(Msg::Ctl(Request {
+            command,
+            reply_sender,
+        }))
+        .expect(
+            "TODO: figure out what to do about channels potentially failing",
+        );
+
+    if command.is_follow() {
+        // Keep sending status updates until either the client goes away, or
+        // break-time is shutting down.
+        for reply in reply_receiver {
+            write_reply(&mut stream, reply)?;
+        }
+        Ok(())
+    } else {
+        let reply = reply_receiver
+            .recv_timeout(Duration::from_secs(REPLY_TIMEOUT_SECONDS))
+            .unwrap_or_else(|_| {
+                Err(String::from("timed out waiting for break-time"))
+            });
+        write_reply(&mut stream, reply)
+    }
+}
+
+fn write_reply(
+    stream: &mut UnixStream,
+    reply: Result<String, String>,
+) -> std::io::Result<()> {
+    match reply {
+        Ok(msg) => writeln!(stream, "ok {}", msg),
+        Err(msg) => writeln!(stream, "error {}", msg),
+    }
+}
+
+fn connect(command: Command) -> Result<BufReader<UnixStream>, String> {
+    let path = socket_path().map_err(|()| {
+        String::from("could not figure out the path for the control socket, is $XDG_RUNTIME_DIR set?")
+    })?;
+
+    let mut stream = UnixStream::connect(&path).map_err(|err| {
+        format!(
+            "could not connect to break-time at {:?}, is it running? ({})",
+            path, err
+        )
+    })?;
+
+    writeln!(stream, "{}", command.as_str()).map_err(|err| {
+        format!("could not send command to break-time: {}", err)
+    })?;
+
+    Ok(BufReader::new(stream))
+}
+
+fn read_reply(reader: &mut BufReader<UnixStream>) -> Result<String, String> {
+    let mut line = String::new();
+    let bytes_read = reader.read_line(&mut line).map_err(|err| {
+        format!("could not read reply from break-time: {}", err)
+    })?;
+    if bytes_read == 0 {
+        return Err(String::from("break-time closed the connection"));
+    }
+
+    parse_reply(line.trim_end())
+}
+
+/// Send a command to the running break-time over the control socket, and
+/// return its reply.
+pub fn send_command(command: Command) -> Result<String, String> {
+    let mut reader = connect(command)?;
+    read_reply(&mut reader)
+}
+
+/// Follow the status of the running break-time, calling `f` with the status
+/// every time it changes.
+///
+/// This only returns when there is an error or break-time exits, and it
+/// returns a message saying what happened.
+pub fn follow_status<F>(format: StatusFormat, mut f: F) -> String
+where
+    F: FnMut(&str),
+{
+    let mut reader = match connect(Command::Follow(format)) {
+        Err(err) => return err,
+        Ok(reader) => reader,
+    };
+
+    loop {
+        match read_reply(&mut reader) {
+            Err(err) => return err,
+            Ok(status) => f(&status),
+        }
+    }
+}
+
+fn parse_reply(line: &str) -> Result<String, String> {
+    let (status, msg) = match line.find(' ') {
+        None => (line, ""),
+        Some(i) => (&line[..i], &line[i + 1..]),
+    };
+    match status {
+        "ok" => Ok(String::from(msg)),
+        "error" => Err(String::from(msg)),
+        _ => Err(format!("unexpected reply from break-time: {}", line)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_round_trip() {
+        let commands = [
+            Command::Pause,
+            Command::Resume,
+            Command::BreakNow,
+            Command::Status(StatusFormat::Text),
+            Command::Status(StatusFormat::Json),
+            Command::Follow(StatusFormat::Text),
+            Command::Follow(StatusFormat::Json),
+            Command::Skip,
+        ];
+        for command in &commands {
+            assert_eq!(Command::from_str(command.as_str()), Some(*command));
+        }
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        assert_eq!(
+            parse_reply("ok 10 minutes until next break"),
+            Ok(String::from("10 minutes until next break"))
+        );
+        assert_eq!(parse_reply("ok"), Ok(String::new()));
+        assert_eq!(
+            parse_reply("error already paused"),
+            Err(String::from("already paused"))
+        );
+        assert!(parse_reply("garbage").is_err());
+    }
+}
@@ -0,0 +1,316 @@
+// This module keeps a history of all the breaks that have happened.
+//
+// Each break is appended as a single line of JSON to a file in the cache directory.  The
+// `break-time stats` command reads this file back in and summarizes it per day and per week.
+
+use chrono::{DateTime, Datelike, Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::config::BreakTier;
+use super::scheduler::unix_time_now;
+use crate::prelude::*;
+
+const HISTORY_FILE_NAME: &str = "break-history.jsonl";
+
+/// How a break ended.
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakEnd {
+    /// The break lasted for the full break duration.
+    Timeout,
+    /// The break was ended early by pressing the spacebar.
+    Skipped,
+    /// The break was postponed.
+    Postponed,
+    /// The user was idle, and this was counted as a break.
+    IdleCredited,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BreakRecord {
+    /// Unix time (in seconds) when the break started.
+    pub start: u64,
+    /// The name of the break tier.  This is `None` for breaks that were
+    /// credited from idle time.
+    pub tier: Option<String>,
+    pub planned_duration_seconds: Option<u64>,
+    pub actual_duration_seconds: u64,
+    pub ended_by: BreakEnd,
+    /// The names of the plugins that delayed this break.
+    pub delayed_by: Vec<String>,
+}
+
+fn path(cache_dir: &Path) -> PathBuf {
+    cache_dir.join(HISTORY_FILE_NAME)
+}
+
+pub fn append(cache_dir: &Path, record: &BreakRecord) -> std::io::Result<()> {
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+
+    let mut history_file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path(cache_dir))?;
+    history_file.write_all(line.as_bytes())
+}
+
+/// Load all the break records.  Lines that can't be parsed are skipped.
+pub fn load(cache_dir: &Path) -> std::io::Result<Vec<BreakRecord>> {
+    let history_file = match std::fs::read_to_string(path(cache_dir)) {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(vec![])
+        }
+        res => res?,
+    };
+
+    let records = history_file
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(line_index, line)| match serde_json::from_str(line) {
+            Err(err) => {
+                println!(
+                    "Skipping line {} of the break history, because it could not be parsed: {}",
+                    line_index + 1,
+                    err
+                );
+                None
+            }
+            Ok(record) => Some(record),
+        })
+        .collect();
+
+    Ok(records)
+}
+
+struct CurrentBreak {
+    start: u64,
+    tier: String,
+    planned_duration_seconds: u64,
+}
+
+/// Keeps track of the break that is currently happening, and writes a record
+/// to the history when it ends.
+pub struct Recorder {
+    cache_dir: PathBuf,
+    current_break: Option<CurrentBreak>,
+    /// The plugins that have delayed the next break.
+    delayed_by: Vec<String>,
+}
+
+impl Recorder {
+    pub const fn new(cache_dir: PathBuf) -> Self {
+        Self {
+            cache_dir,
+            current_break: None,
+            delayed_by: vec![],
+        }
+    }
+
+    pub fn break_blocked(&mut self, blocking_plugins: &[String]) {
+        for plugin in blocking_plugins {
+            if !self.delayed_by.contains(plugin) {
+                self.delayed_by.push(plugin.clone());
+            }
+        }
+    }
+
+    pub fn break_started(&mut self, break_tier: &BreakTier) {
+        self.current_break = Some(CurrentBreak {
+            start: unix_time_now(),
+            tier: break_tier.name.clone(),
+            planned_duration_seconds: break_tier.break_duration_seconds.into(),
+        });
+    }
+
+    pub fn break_ended(&mut self, ended_by: BreakEnd) {
+        if let Some(current_break) = self.current_break.take() {
+            let record = BreakRecord {
+                start: current_break.start,
+                tier: Some(current_break.tier),
+                planned_duration_seconds: Some(
+                    current_break.planned_duration_seconds,
+                ),
+                actual_duration_seconds: unix_time_now()
+                    .saturating_sub(current_break.start),
+                ended_by,
+                delayed_by: std::mem::take(&mut self.delayed_by),
+            };
+            self.write(&record);
+        }
+    }
+
+    pub fn idle_credited(&mut self, idle_time: Duration) {
+        let record = BreakRecord {
+            start: unix_time_now().saturating_sub(idle_time.as_secs()),
+            tier: None,
+            planned_duration_seconds: None,
+            actual_duration_seconds: idle_time.as_secs(),
+            ended_by: BreakEnd::IdleCredited,
+            delayed_by: std::mem::take(&mut self.delayed_by),
+        };
+        self.write(&record);
+    }
+
+    fn write(&self, record: &BreakRecord) {
+        if let Err(err) = append(&self.cache_dir, record) {
+            println!("Could not write to the break history: {}", err);
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Summary {
+    pub taken: u32,
+    pub skipped: u32,
+    pub postponed: u32,
+    pub idle_credited: u32,
+    pub break_seconds: u64,
+}
+
+impl Summary {
+    fn add(&mut self, record: &BreakRecord) {
+        match record.ended_by {
+            BreakEnd::Timeout => self.taken += 1,
+            BreakEnd::Skipped => self.skipped += 1,
+            BreakEnd::Postponed => self.postponed += 1,
+            BreakEnd::IdleCredited => self.idle_credited += 1,
+        }
+        self.break_seconds += record.actual_duration_seconds;
+    }
+}
+
+/// Summarize the break records, grouping them by the key returned from
+/// `get_key` for the start time of each record.
+pub fn summarize<F>(
+    records: &[BreakRecord],
+    get_key: F,
+) -> BTreeMap<String, Summary>
+where
+    F: Fn(u64) -> String,
+{
+    let mut summaries: BTreeMap<String, Summary> = BTreeMap::new();
+    for record in records {
+        summaries
+            .entry(get_key(record.start))
+            .or_default()
+            .add(record);
+    }
+    summaries
+}
+
+fn start_time<Tz: TimeZone>(timezone: &Tz, start: u64) -> Option<DateTime<Tz>> {
+    let start = i64::try_from(start).ok()?;
+    timezone.timestamp_opt(start, 0).single()
+}
+
+fn day_key<Tz: TimeZone>(timezone: &Tz, start: u64) -> String {
+    start_time(timezone, start).map_or_else(
+        || String::from("unknown"),
+        |start_time| start_time.naive_local().format("%Y-%m-%d").to_string(),
+    )
+}
+
+fn week_key<Tz: TimeZone>(timezone: &Tz, start: u64) -> String {
+    start_time(timezone, start).map_or_else(
+        || String::from("unknown"),
+        |start_time| {
+            let week = start_time.iso_week();
+            format!("{}-W{:02}", week.year(), week.week())
+        },
+    )
+}
+
+fn print_summaries(title: &str, summaries: &BTreeMap<String, Summary>) {
+    println!("{}:", title);
+    for (key, summary) in summaries {
+        println!(
+            "  {:<10}  {:>3} taken, {:>3} skipped, {:>3} postponed, {:>3} idle, {} minutes of breaks",
+            key,
+            summary.taken,
+            summary.skipped,
+            summary.postponed,
+            summary.idle_credited,
+            summary.break_seconds / 60,
+        );
+    }
+}
+
+pub fn print_stats(cache_dir: &Path) {
+    let records = match load(cache_dir) {
+        Err(err) => {
+            println!("Could not read the break history: {}", err);
+            return;
+        }
+        Ok(records) => records,
+    };
+
+    if records.is_empty() {
+        println!("No breaks have been recorded yet.");
+        return;
+    }
+
+    print_summaries(
+        "Per day",
+        &summarize(&records, |start| day_key(&Local, start)),
+    );
+    println!();
+    print_summaries(
+        "Per week",
+        &summarize(&records, |start| week_key(&Local, start)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn record(start: u64, ended_by: BreakEnd, actual: u64) -> BreakRecord {
+        BreakRecord {
+            start,
+            tier: Some(String::from("break")),
+            planned_duration_seconds: Some(600),
+            actual_duration_seconds: actual,
+            ended_by,
+            delayed_by: vec![],
+        }
+    }
+
+    #[test]
+    fn test_summarize() {
+        // 2020-06-01 is a Monday.
+        let monday = 1_590_969_600;
+        let tuesday = monday + 24 * 60 * 60;
+        let next_monday = monday + 7 * 24 * 60 * 60;
+        let records = vec![
+            record(monday + 100, BreakEnd::Timeout, 600),
+            record(monday + 5000, BreakEnd::Skipped, 30),
+            record(tuesday, BreakEnd::IdleCredited, 1200),
+            record(next_monday, BreakEnd::Postponed, 10),
+        ];
+
+        let per_day = summarize(&records, |start| day_key(&Utc, start));
+        assert_eq!(per_day.len(), 3);
+        assert_eq!(
+            per_day["2020-06-01"],
+            Summary {
+                taken: 1,
+                skipped: 1,
+                postponed: 0,
+                idle_credited: 0,
+                break_seconds: 630,
+            }
+        );
+
+        let per_week = summarize(&records, |start| week_key(&Utc, start));
+        assert_eq!(per_week.len(), 2);
+        assert_eq!(per_week["2020-W23"].break_seconds, 1830);
+        assert_eq!(per_week["2020-W24"].postponed, 1);
+    }
+}
@@ -8,10 +8,14 @@
     clippy::match_same_arms
 )]
 
+mod clock;
 mod config;
+mod ctl;
+mod history;
 mod opts;
 mod prelude;
 mod scheduler;
+mod status;
 mod tray;
 pub mod ui;
 mod x11;
@@ -19,71 +23,321 @@ mod x11;
 use std::sync::mpsc::Sender;
 use std::time::Duration;
 
-use config::Config;
+use config::{BreakTier, Config, Settings};
 use scheduler::Scheduler;
+use status::{State, Status};
 use tray::Tray;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub enum Msg {
-    EndBreak,
+    EndBreak(history::BreakEnd),
     Pause,
     Quit,
     ResetSysTrayIcon,
     Resume,
-    StartBreak,
+    StartBreak(BreakTier),
     TimeRemainingBeforeBreak(Duration),
     EnableIdleDetector,
     DisableIdleDetector,
+    Postpone(Duration),
+    /// The plugins with these names are stopping a break from starting, and
+    /// the break has been delayed for this amount of time.  The list of
+    /// plugins is empty once the break is no longer blocked.
+    BreakBlocked(Vec<String>, Duration),
+    /// The plugins have been asked whether a break can happen.
+    PluginsChecked(Vec<scheduler::plugin_runner::PluginResult>),
+    /// A break is starting even though the plugins with these names said no,
+    /// because they have delayed it for longer than their
+    /// `max_deferral_seconds`.
+    MaxDeferralExceeded(Vec<String>),
+    /// The user was idle for this amount of time, and this was counted as a
+    /// break.
+    IdleCredited(Duration),
+    Ctl(ctl::Request),
+    /// The config file has changed.  This is an error message if the new
+    /// config file is invalid.
+    ConfigFileChanged(Result<Box<Settings>, String>),
 }
 
+/// Keeps track of how many times in a row a break has been postponed.
+struct Postpones {
+    in_a_row: u32,
+    max_in_a_row: u32,
+}
+
+impl Postpones {
+    const fn new(config: &Config) -> Self {
+        Self {
+            in_a_row: 0,
+            max_in_a_row: config.settings.max_postpones_in_a_row,
+        }
+    }
+
+    const fn can_postpone(&self) -> bool {
+        self.in_a_row < self.max_in_a_row
+    }
+}
+
+fn handle_ctl_command(
+    sender: &glib::Sender<Msg>,
+    scheduler_inner_sender: &Sender<scheduler::InnerMsg>,
+    status: &Status,
+    followers: &mut ctl::Followers,
+    request: ctl::Request,
+) {
+    let reply = match request.command {
+        ctl::Command::Status(ctl::StatusFormat::Text) => Ok(status.to_text()),
+        ctl::Command::Status(ctl::StatusFormat::Json) => Ok(status.to_json()),
+        ctl::Command::Follow(_) => {
+            // Followers get the current status right away, and then again
+            // every time it changes.
+            followers.add(request);
+            followers.send_status(status);
+            return;
+        }
+        _ if status.is_break_in_progress() => {
+            Err(String::from("a break is in progress"))
+        }
+        ctl::Command::Pause => {
+            if status.is_paused() {
+                Err(String::from("break-time is already paused"))
+            } else {
+                sender.send(Msg::Pause).expect("TODO: figure out what to do about channels potentially failing");
+                Ok(String::from("paused"))
+            }
+        }
+        ctl::Command::Resume => {
+            if status.is_paused() {
+                sender.send(Msg::Resume).expect("TODO: figure out what to do about channels potentially failing");
+                Ok(String::from("resumed"))
+            } else {
+                Err(String::from("break-time is not paused"))
+            }
+        }
+        _ if status.is_paused() => Err(String::from("break-time is paused")),
+        ctl::Command::BreakNow => {
+            scheduler_inner_sender.send(scheduler::InnerMsg::BreakNow).expect("TODO: figure out what to do about channels potentially failing");
+            Ok(String::from("starting a break"))
+        }
+        ctl::Command::Skip => {
+            scheduler_inner_sender.send(scheduler::InnerMsg::Skip).expect("TODO: figure out what to do about channels potentially failing");
+            Ok(String::from("skipped the next break"))
+        }
+    };
+    request.reply(reply);
+}
+
+/// Start using the settings from a config file that has been reloaded.
+fn reload_config(
+    config: &mut Config,
+    sender: &glib::Sender<Msg>,
+    scheduler_inner_sender: &Sender<scheduler::InnerMsg>,
+    tray: &mut Tray,
+    postpones: &mut Postpones,
+    settings: Settings,
+) {
+    // Editors often write a file more than once when saving it.
+    if settings == config.settings {
+        return;
+    }
+
+    println!("Reloading config file {:?}", config.file_path);
+    for warning in settings.warnings() {
+        println!("Warning: {}", warning);
+    }
+
+    let idle_detection_enabled_changed = settings.idle_detection_enabled
+        != config.settings.idle_detection_enabled;
+    config.settings = settings;
+
+    postpones.max_in_a_row = config.settings.max_postpones_in_a_row;
+    tray.set_can_postpone(postpones.can_postpone());
+    tray.set_postpone_duration(Duration::from_secs(
+        config.settings.postpone_seconds.into(),
+    ));
+
+    if idle_detection_enabled_changed {
+        let msg = if config.settings.idle_detection_enabled {
+            Msg::EnableIdleDetector
+        } else {
+            Msg::DisableIdleDetector
+        };
+        sender.send(msg).expect(
+            "TODO: figure out what to do about channels potentially failing",
+        );
+    }
+
+    // The scheduler only gets this once it is counting down to the next
+    // break again, so a break or pause that is happening right now isn't
+    // affected.
+    scheduler_inner_sender
+        .send(scheduler::InnerMsg::ReloadConfig(Box::new(config.clone())))
+        .expect(
+            "TODO: figure out what to do about channels potentially failing",
+        );
+}
+
+/// Tell the user about plugins that have been disabled because they kept
+/// failing.
+fn notify_plugin_results(
+    tray: &Tray,
+    plugin_results: &[scheduler::plugin_runner::PluginResult],
+) {
+    for plugin_result in plugin_results {
+        if plugin_result.disabled {
+            tray.notify(
+                &format!("break-time: plugin {} disabled", plugin_result.name),
+                &format!(
+                    "The plugin kept failing ({}), so it won't be asked whether a break can happen until break-time is restarted or the plugin settings change.",
+                    plugin_result.answer
+                ),
+            );
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::too_many_lines)]
 fn handle_msg_recv(
-    config: &Config,
+    config: &mut Config,
     sender: glib::Sender<Msg>,
     scheduler_outer_sender: &Sender<scheduler::Msg>,
     scheduler_inner_sender: &Sender<scheduler::InnerMsg>,
     tray: &mut Tray,
+    postpones: &mut Postpones,
+    status: &mut Status,
+    followers: &mut ctl::Followers,
+    recorder: &mut history::Recorder,
     msg: Msg,
 ) {
     match msg {
-        Msg::EndBreak => {
-            println!("break ended");
+        Msg::EndBreak(break_end) => {
+            println!("break ended: {:?}", break_end);
+            recorder.break_ended(break_end);
+            postpones.in_a_row = 0;
+            status.set_state(State::CountDownToBreak);
+            tray.set_can_postpone(postpones.can_postpone());
             tray.break_end();
             scheduler_outer_sender.send(scheduler::Msg::Start).expect("TODO: figure out what to do about channels potentially failing");
         }
         Msg::Pause => {
+            status.set_state(State::Paused);
             tray.pause();
             scheduler_inner_sender.send(scheduler::InnerMsg::Pause).expect("TODO: figure out what to do about channels potentially failing");
         }
         Msg::Quit => {
             gtk::main_quit();
         }
-        Msg::StartBreak => {
-            println!("starting break");
+        Msg::StartBreak(break_tier) => {
+            println!("starting break: {}", break_tier.name);
+            status.set_state(State::WaitingForBreakEnd);
+            recorder.break_started(&break_tier);
             tray.render_break_starting();
-            ui::start_break(config, sender);
+            ui::start_break(
+                config,
+                &break_tier,
+                sender,
+                postpones.can_postpone(),
+            );
         }
         Msg::ResetSysTrayIcon => {
             tray.render_normal_icon();
         }
         Msg::Resume => {
+            status.set_state(State::CountDownToBreak);
             tray.resume();
             scheduler_outer_sender.send(scheduler::Msg::Start).expect("TODO: figure out what to do about channels potentially failing");
         }
         Msg::TimeRemainingBeforeBreak(remaining_time) => {
+            status.seconds_until_break = Some(remaining_time.as_secs());
             tray.update_time_remaining(remaining_time);
         }
         Msg::EnableIdleDetector => {
+            status.idle_detection_enabled = true;
             tray.set_is_idle_detector_enabled(tray::IsIdleDetectorEnabled::Yes);
             scheduler_inner_sender.send(scheduler::InnerMsg::EnableIdleDetector).expect("TODO: figure out what to do about channels potentially failing");
         }
         Msg::DisableIdleDetector => {
+            status.idle_detection_enabled = false;
             tray.set_is_idle_detector_enabled(tray::IsIdleDetectorEnabled::No);
             scheduler_inner_sender.send(scheduler::InnerMsg::DisableIdleDetector).expect("TODO: figure out what to do about channels potentially failing");
         }
+        Msg::Postpone(postpone_time) => {
+            // The break screen and the systray only let the user postpone when this is possible,
+            // but check again just to be safe.
+            if !postpones.can_postpone() {
+                println!("Not postponing break, since it has already been postponed {} times in a row", postpones.in_a_row);
+                return;
+            }
+            println!("postponing break by {:?}", postpone_time);
+            postpones.in_a_row += 1;
+            tray.set_can_postpone(postpones.can_postpone());
+            if status.is_break_in_progress() {
+                recorder.break_ended(history::BreakEnd::Postponed);
+                status.set_state(State::CountDownToBreak);
+                tray.break_end();
+                scheduler_outer_sender.send(scheduler::Msg::Postpone(postpone_time)).expect("TODO: figure out what to do about channels potentially failing");
+            } else {
+                scheduler_inner_sender.send(scheduler::InnerMsg::Postpone(postpone_time)).expect("TODO: figure out what to do about channels potentially failing");
+            }
+        }
+        Msg::BreakBlocked(blocking_plugins, deferral) => {
+            println!(
+                "break blocked by plugins: {:?}, delayed for {:?}",
+                blocking_plugins, deferral
+            );
+            recorder.break_blocked(&blocking_plugins);
+            tray.set_blocking_plugins(blocking_plugins.clone());
+            status.set_blocking_plugins(blocking_plugins, deferral);
+        }
+        Msg::PluginsChecked(plugin_results) => {
+            notify_plugin_results(tray, &plugin_results);
+            status.plugin_results = plugin_results;
+        }
+        Msg::MaxDeferralExceeded(plugin_names) => {
+            tray.notify(
+                "break-time: starting a break anyway",
+                &format!(
+                    "{} delayed this break for longer than max_deferral_seconds, so the break is starting even though {} said no.",
+                    plugin_names.join(", "),
+                    if plugin_names.len() == 1 { "it" } else { "they" }
+                ),
+            );
+        }
+        Msg::IdleCredited(idle_time) => {
+            println!("idle for {:?}, counting it as a break", idle_time);
+            recorder.idle_credited(idle_time);
+        }
+        Msg::ConfigFileChanged(Ok(settings)) => {
+            reload_config(
+                config,
+                &sender,
+                scheduler_inner_sender,
+                tray,
+                postpones,
+                *settings,
+            );
+        }
+        Msg::ConfigFileChanged(Err(err)) => {
+            println!("Not reloading invalid config file: {}", err);
+            tray.notify(
+                "break-time: invalid config file",
+                &format!("{}\n\nKeeping the old settings.", err),
+            );
+        }
+        Msg::Ctl(request) => {
+            println!("got command from control socket: {:?}", request.command);
+            handle_ctl_command(
+                &sender,
+                scheduler_inner_sender,
+                status,
+                followers,
+                request,
+            );
+        }
     }
 }
 
-pub fn run(config: Config) {
+pub fn run(mut config: Config) {
     gtk::init().expect("Could not initialize GTK");
 
     let (sender, receiver) =
@@ -95,15 +349,40 @@ pub fn run(config: Config) {
     let (scheduler_outer_sender, scheduler_inner_sender) =
         Scheduler::run(&config, sender.clone());
 
+    let mut postpones = Postpones::new(&config);
+    let mut status = Status::new(&config);
+    let mut followers = ctl::Followers::default();
+    let mut recorder = history::Recorder::new(config.cache_dir.clone());
+
+    // break-time still works without the control socket, so just keep going
+    // if it can't be set up.
+    let _ = ctl::listen(sender.clone());
+
+    // The same goes for watching the config file for changes.
+    let config_file_sender = sender.clone();
+    let _ = config::watch(config.file_path.clone(), move |res_settings| {
+        let res_settings =
+            res_settings.map(Box::new).map_err(|err| err.to_string());
+        config_file_sender.send(Msg::ConfigFileChanged(res_settings)).expect("TODO: figure out what to do about channels potentially failing");
+    });
+
     receiver.attach(None, move |msg| {
+        let old_status = status.clone();
         handle_msg_recv(
-            &config,
+            &mut config,
             sender.clone(),
             &scheduler_outer_sender,
             &scheduler_inner_sender,
             &mut tray,
+            &mut postpones,
+            &mut status,
+            &mut followers,
+            &mut recorder,
             msg,
         );
+        if status != old_status {
+            followers.send_status(&status);
+        }
         glib::source::Continue(true)
     });
 
@@ -126,15 +405,101 @@ pub fn run_google_calendar_command(
     }
 }
 
+pub fn run_ctl_command(ctl_command: opts::Ctl) {
+    let command = match ctl_command {
+        opts::Ctl::Pause => ctl::Command::Pause,
+        opts::Ctl::Resume => ctl::Command::Resume,
+        opts::Ctl::BreakNow => ctl::Command::BreakNow,
+        opts::Ctl::Status => ctl::Command::Status(ctl::StatusFormat::Text),
+        opts::Ctl::Skip => ctl::Command::Skip,
+    };
+
+    match ctl::send_command(command) {
+        Ok(msg) => println!("{}", msg),
+        Err(err) => {
+            eprintln!("break-time ctl: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+pub fn run_status_command(status_command: &opts::Status) {
+    let format = if status_command.json {
+        ctl::StatusFormat::Json
+    } else {
+        ctl::StatusFormat::Text
+    };
+
+    let err = if status_command.follow {
+        ctl::follow_status(format, |status| println!("{}", status))
+    } else {
+        match ctl::send_command(ctl::Command::Status(format)) {
+            Ok(status) => {
+                println!("{}", status);
+                return;
+            }
+            Err(err) => err,
+        }
+    };
+
+    eprintln!("break-time status: {}", err);
+    std::process::exit(1);
+}
+
+pub fn run_config_check_command(opts: &opts::Opts) {
+    let res_settings = Config::file_path(opts).and_then(|config_file_path| {
+        Settings::read(&config_file_path)
+            .map(|settings| (config_file_path, settings))
+    });
+    let (config_file_path, settings) = match res_settings {
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+        Ok(res) => res,
+    };
+
+    let warnings = settings.warnings();
+    for warning in &warnings {
+        eprintln!("warning: {:?}: {}", config_file_path, warning);
+    }
+    if !warnings.is_empty() {
+        std::process::exit(2);
+    }
+
+    println!("{:?}: ok", config_file_path);
+}
+
 pub fn default_main() {
     let opts = opts::Opts::parse_from_args();
 
-    let config = Config::load(&opts).expect("Could not load config file.");
+    // `config check` reports the problems with the config file itself, so it
+    // can't start by loading the config.
+    if let Some(opts::Command::Config(opts::Config::Check)) = opts.cmd {
+        run_config_check_command(&opts);
+        return;
+    }
+
+    let config = Config::load(&opts).unwrap_or_else(|err| {
+        eprintln!("Could not load config file: {}", err);
+        std::process::exit(1);
+    });
+    for warning in config.settings.warnings() {
+        println!("Warning: {}", warning);
+    }
 
     match opts.cmd {
         None => run(config),
+        Some(opts::Command::Config(opts::Config::Check)) => {
+            unreachable!("config check is handled before loading the config")
+        }
         Some(opts::Command::GoogleCalendar(google_calendar_command)) => {
             run_google_calendar_command(&config, google_calendar_command);
         }
+        Some(opts::Command::Ctl(ctl_command)) => run_ctl_command(ctl_command),
+        Some(opts::Command::Status(status_command)) => {
+            run_status_command(&status_command);
+        }
+        Some(opts::Command::Stats) => history::print_stats(&config.cache_dir),
     }
 }
@@ -27,6 +27,15 @@ impl Opts {
 #[derive(Debug, StructOpt)]
 pub enum Command {
     GoogleCalendar(GoogleCalendar),
+    /// Commands for working with the config file.
+    Config(Config),
+    /// Control an already-running break-time.
+    Ctl(Ctl),
+    /// Show the status of an already-running break-time.
+    Status(Status),
+    /// Show how many breaks have been taken, skipped, and postponed, per day
+    /// and per week.
+    Stats,
 }
 
 #[derive(Debug, StructOpt)]
@@ -35,6 +44,40 @@ pub enum GoogleCalendar {
     IgnoreEvent(IgnoreEvent),
 }
 
+#[derive(Debug, StructOpt)]
+pub enum Config {
+    /// Check the config file for errors and settings that don't make sense.
+    /// Exits with 1 if there are errors, and 2 if there are only warnings.
+    Check,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Ctl {
+    /// Pause break-time, so that no breaks happen until it is resumed.
+    Pause,
+    /// Resume break-time after it has been paused.
+    Resume,
+    /// Start a break right now.
+    BreakNow,
+    /// Show how long until the next break.
+    Status,
+    /// Skip the next break.  The countdown starts again as if the break had
+    /// been taken.
+    Skip,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct Status {
+    /// Output the status as JSON.  This is meant for status bars, like waybar, polybar, or
+    /// i3blocks.
+    #[structopt(long)]
+    pub json: bool,
+
+    /// Keep running, and output a new line every time the status changes.
+    #[structopt(long)]
+    pub follow: bool,
+}
+
 #[derive(Debug, StructOpt)]
 pub struct IgnoreEvent {
     /// Event ID.  You can get this with `break-time google-calendar list-events`.
@@ -1,74 +1,106 @@
 // This code is pretty horrible.  I am sorry.
 
+mod break_tiers;
+mod events;
 mod idle_detector;
+mod logind;
+pub mod plugin_runner;
 pub mod plugins;
+mod saved_state;
+#[cfg(test)]
+mod simulation;
+
+use super::config::{Config, IdleCreditMode};
+use break_tiers::Countdowns;
+use events::{Channels, Clock, EventSource, Outbox, SystemClock};
+use idle_detector::{IdleDetectionTime, IdleDetector};
+use plugin_runner::{PluginResult, PluginRunner};
+use saved_state::SavedState;
 
-use super::config::Config;
-use idle_detector::IdleDetector;
-use plugins::{CanBreak, Plugin};
+pub use saved_state::unix_time_now;
 
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+/// How often the countdowns are saved while they are counting down.  They
+/// are also saved whenever they restart.
+const SAVE_STATE_INTERVAL: Duration = Duration::from_secs(5 * 60);
 
 #[derive(Copy, Clone, Debug)]
 pub enum Msg {
     Start,
+    /// Start counting down to the next break, but only wait for the given
+    /// amount of time instead of the full `seconds_between_breaks`.
+    Postpone(Duration),
 }
 
-pub struct Plugins(Vec<Box<dyn Plugin>>);
+pub struct Plugins(Vec<PluginRunner>);
 
 impl Plugins {
     fn new(config: &Config) -> Result<Self, ()> {
+        let plugin_settings = &config.settings.all_plugin_settings;
+        let default_policy = &plugin_settings.default_policy;
+
         let window_title_plugin = plugins::WindowTitles::new(config)?;
+        let processes_plugin = plugins::Processes::new(config)?;
         let google_calendar_plugin = plugins::GoogleCalendar::new(config)?;
-        let all_plugins: Vec<Box<dyn Plugin>> = vec![
-            Box::new(window_title_plugin),
-            Box::new(google_calendar_plugin),
-        ];
-        Ok(Self(all_plugins))
-    }
-
-    fn can_break_now(
-        &self,
-    ) -> (Option<CanBreak>, Vec<Box<dyn std::error::Error>>) {
-        fn f(
-            (opt_old_can_break, mut err_accum): (
-                Option<CanBreak>,
-                Vec<Box<dyn std::error::Error>>,
+        let mut all_plugins: Vec<PluginRunner> = vec![
+            PluginRunner::new(
+                Arc::new(window_title_plugin),
+                plugin_settings
+                    .x11_window_title_checker
+                    .policy
+                    .resolve(default_policy),
             ),
-            plugin: &dyn Plugin,
-        ) -> (Option<CanBreak>, Vec<Box<dyn std::error::Error>>) {
-            let res_can_break = plugin.can_break_now();
-            match res_can_break {
-                Err(err) => {
-                    err_accum.push(err);
-                    (opt_old_can_break, err_accum)
-                }
-                Ok(can_break) => {
-                    let new_can_break = opt_old_can_break
-                        .map_or(can_break, |old_can_break| {
-                            can_break.combine(old_can_break)
-                        });
-                    (Some(new_can_break), err_accum)
-                }
-            }
+            PluginRunner::new(
+                Arc::new(processes_plugin),
+                plugin_settings.processes.policy.resolve(default_policy),
+            ),
+            PluginRunner::new(
+                Arc::new(google_calendar_plugin),
+                plugin_settings
+                    .google_calendar
+                    .policy
+                    .resolve(default_policy),
+            ),
+            PluginRunner::new(
+                Arc::new(plugins::Ics::new(config)),
+                plugin_settings.ics.policy.resolve(default_policy),
+            ),
+        ];
+        if plugin_settings.capture_devices.enabled {
+            all_plugins.push(PluginRunner::new(
+                Arc::new(plugins::CaptureDevices::new(config)),
+                plugin_settings
+                    .capture_devices
+                    .policy
+                    .resolve(default_policy),
+            ));
         }
-
-        // TODO: I probably want to parallelize calling can_break_now()
-        // for each of the plugins, because they may take a non-trivial
-        // amount of time deciding whether or not to break.
-        self.iter()
-            .fold((None, vec![]), |accum, plugin| f(accum, plugin))
+        if plugin_settings.fullscreen.enabled {
+            all_plugins.push(PluginRunner::new(
+                Arc::new(plugins::Fullscreen::new(config)?),
+                plugin_settings.fullscreen.policy.resolve(default_policy),
+            ));
+        }
+        for command_settings in &plugin_settings.command {
+            all_plugins.push(PluginRunner::new(
+                Arc::new(plugins::Command::new(command_settings)),
+                command_settings.policy.resolve(default_policy),
+            ));
+        }
+        Ok(Self(all_plugins))
     }
-}
-
-impl std::ops::Deref for Plugins {
-    type Target = [Box<dyn Plugin>];
 
-    fn deref(&self) -> &[Box<dyn Plugin>] {
-        &self.0
+    /// Ask each of the plugins whether or not we can break now.  The plugins
+    /// are all asked at the same time.
+    ///
+    /// `deferral` is how long the plugins have already been delaying the
+    /// break.
+    fn can_break_now(&mut self, deferral: Duration) -> Vec<PluginResult> {
+        plugin_runner::check_all(&mut self.0, deferral)
     }
 }
 
@@ -78,42 +110,44 @@ enum State {
     WaitingForBreakEnd,
 }
 
-pub struct Scheduler {
+/// The scheduler decides when breaks happen.
+///
+/// It doesn't start any threads or read any real clocks itself.  Time and
+/// messages come from `clock` and `events`, and all messages for the rest of
+/// break-time go to `sender`.
+pub struct Scheduler<C: Clock, E: EventSource, O: Outbox> {
+    config: Config,
     idle_detection_enabled: Arc<AtomicBool>,
-    sender: glib::Sender<super::Msg>,
+    idle_detection_time: Arc<IdleDetectionTime>,
+    clock: C,
+    events: E,
+    sender: O,
     plugins: Plugins,
-    time_until_break: Duration,
-    break_ending_receiver: Receiver<Msg>,
-    restart_wait_time_receiver: Receiver<InnerMsg>,
+    countdowns: Countdowns,
+    /// The index of the tier of the break that is currently happening.
+    current_break_tier: Option<usize>,
+    /// Unix time (in seconds) when the last break ended.
+    last_break_end: Option<u64>,
+    /// When a break has been postponed, this is the amount of time left to
+    /// wait until the next break.  It counts down as time passes, and is
+    /// cleared once it has been used up, or the break is taken or skipped.
+    postponed_time_until_break: Option<Duration>,
+    /// When plugins first stopped the next break from starting.  This is
+    /// `None` if the next break hasn't been blocked.
+    blocked_since: Option<Instant>,
+    /// When the countdowns were last saved to the cache directory.
+    last_state_save: Instant,
     state: State,
 }
 
 enum WaitUntilBreakResult {
     FinishedWaiting,
     Paused,
+    /// There will never be any more messages from `events`.
+    Stopped,
 }
 
-impl Scheduler {
-    pub fn new(
-        config: &Config,
-        idle_detection_enabled: Arc<AtomicBool>,
-        sender: glib::Sender<super::Msg>,
-        break_ending_receiver: Receiver<Msg>,
-        restart_wait_time_receiver: Receiver<InnerMsg>,
-    ) -> Result<Self, ()> {
-        Ok(Self {
-            idle_detection_enabled,
-            sender,
-            plugins: Plugins::new(&config)?,
-            time_until_break: Duration::from_secs(
-                config.settings.seconds_between_breaks.into(),
-            ),
-            break_ending_receiver,
-            restart_wait_time_receiver,
-            state: State::CountDownToBreak,
-        })
-    }
-
+impl Scheduler<SystemClock, Channels, glib::Sender<super::Msg>> {
     pub fn run(
         config: &Config,
         sender: glib::Sender<super::Msg>,
@@ -125,32 +159,93 @@ impl Scheduler {
         let idle_detection_enabled =
             Arc::new(AtomicBool::new(config.settings.idle_detection_enabled));
         let idle_detection_enabled_clone = idle_detection_enabled.clone();
+        let idle_detection_time = Arc::new(IdleDetectionTime::new(
+            config.settings.idle_detection_seconds,
+        ));
+        let idle_detection_time_clone = idle_detection_time.clone();
         std::thread::spawn(move || {
             // TODO: Need to actually handle this error.
+            let plugins = Plugins::new(&config_clone)
+                .expect("Could not initialize plugins.");
+            println!("Scheduler initialized plugins");
             let mut sched = Self::new(
                 &config_clone,
+                plugins,
                 idle_detection_enabled_clone,
+                idle_detection_time_clone,
+                SystemClock,
+                Channels {
+                    break_ending_receiver: sched_break_ending_receiver,
+                    restart_wait_time_receiver,
+                },
                 sender,
-                sched_break_ending_receiver,
-                restart_wait_time_receiver,
-            )
-            .expect("Could not initialize plugins.");
-            println!("Scheduler initialized plugins");
+            );
             sched.run_loop();
+            println!("Scheduler stopped, because nothing can send it messages anymore");
+        });
+        let restart_wait_time_sender_clone = restart_wait_time_sender.clone();
+        std::thread::spawn(move || {
+            logind::run(restart_wait_time_sender_clone);
         });
-        let config_clone = config.clone();
         let restart_wait_time_sender_clone = restart_wait_time_sender.clone();
         std::thread::spawn(move || {
             IdleDetector::run(
-                &config_clone,
+                &idle_detection_time,
                 &idle_detection_enabled,
                 restart_wait_time_sender_clone,
             );
         });
         (sched_break_ending_sender, restart_wait_time_sender)
     }
+}
+
+impl<C: Clock, E: EventSource, O: Outbox> Scheduler<C, E, O> {
+    pub fn new(
+        config: &Config,
+        plugins: Plugins,
+        idle_detection_enabled: Arc<AtomicBool>,
+        idle_detection_time: Arc<IdleDetectionTime>,
+        clock: C,
+        events: E,
+        sender: O,
+    ) -> Self {
+        let mut countdowns = Countdowns::new(config.settings.break_tiers());
+
+        // Resume the countdown from the last time break-time was running.
+        let opt_saved_state = SavedState::load(&config.cache_dir);
+        if let Some(saved_state) = &opt_saved_state {
+            let time_off =
+                saved_state.time_since_saved(saved_state::unix_time_now());
+            println!(
+                "Resuming from saved scheduler state, break-time was not running for {:?}",
+                time_off
+            );
+            countdowns.resume(&saved_state.remaining_seconds, time_off);
+        }
+        let now = clock.now();
 
-    fn run_loop(&mut self) -> ! {
+        Self {
+            config: config.clone(),
+            idle_detection_enabled,
+            idle_detection_time,
+            clock,
+            events,
+            sender,
+            plugins,
+            countdowns,
+            current_break_tier: None,
+            last_break_end: opt_saved_state
+                .and_then(|saved_state| saved_state.last_break_end),
+            postponed_time_until_break: None,
+            blocked_since: None,
+            last_state_save: now,
+            state: State::CountDownToBreak,
+        }
+    }
+
+    /// Schedule breaks until there will never be any more messages from
+    /// `events`.
+    fn run_loop(&mut self) {
         loop {
             match self.state {
                 State::CountDownToBreak => {
@@ -162,18 +257,26 @@ impl Scheduler {
                         WaitUntilBreakResult::Paused => {
                             self.state = State::Paused;
                         }
+                        WaitUntilBreakResult::Stopped => return,
                     }
                 }
                 State::Paused | State::WaitingForBreakEnd => {
                     // Wait for a message signalling a break ending or a pause ending.
                     println!("Scheduler currently waiting for a message signaling either a break or a pause ending.");
-                    let msg = self
-                        .break_ending_receiver
-                        .recv()
-                        .expect("Error receiving value in Scheduler.");
+                    let msg = match self.events.recv_break_ending() {
+                        None => return,
+                        Some(msg) => msg,
+                    };
 
                     match msg {
                         Msg::Start => {
+                            self.restart_countdowns();
+                            self.state = State::CountDownToBreak;
+                        }
+                        Msg::Postpone(postpone_time) => {
+                            self.current_break_tier = None;
+                            self.postponed_time_until_break =
+                                Some(postpone_time);
                             self.state = State::CountDownToBreak;
                         }
                     }
@@ -182,57 +285,165 @@ impl Scheduler {
         }
     }
 
+    /// Restart the countdowns after either a break or a pause has ended.
+    fn restart_countdowns(&mut self) {
+        self.postponed_time_until_break = None;
+        match (&self.state, self.current_break_tier.take()) {
+            (State::WaitingForBreakEnd, Some(tier_index)) => {
+                self.countdowns.take_break(tier_index);
+                self.last_break_end = Some(saved_state::unix_time_now());
+            }
+            _ => self.countdowns.restart_all(),
+        }
+        self.save_state();
+    }
+
     fn wait_until_break(&mut self) -> WaitUntilBreakResult {
         loop {
             let waiting_result = self.send_msgs_while_waiting();
             match waiting_result {
                 WaitingResult::Finished => {
+                    // The postponed time, if any, has been used up.
+                    self.postponed_time_until_break = None;
+                    let tier_index = match self.countdowns.due_tier() {
+                        None => {
+                            println!("Scheduler finished sleeping, but no break is due yet, so sleeping again...");
+                            continue;
+                        }
+                        Some(tier_index) => tier_index,
+                    };
                     println!(
-                        "Scheduler successfully finished sleeping, checking if it can break now..."
+                        "Scheduler successfully finished sleeping, checking if it can break now for {}...",
+                        self.countdowns.tier(tier_index).name
                     );
-                    let (opt_can_break, errs) = self.plugins.can_break_now();
-                    if errs.is_empty() {
-                        match opt_can_break {
-                            None => panic!("If there are no errors, then we should always get a response to can_break"),
-                            Some(can_break) => {
-                                if can_break.into_bool() {
-                                    println!("Scheduler realized it was able to break, so sending a message.");
-                                    self.sender.send(super::Msg::StartBreak).expect("TODO: figure out what to do about channels potentially failing");
-                                    return WaitUntilBreakResult::FinishedWaiting;
-                                }
-                                println!("Could not break right now, so sleeping again...");
-                            }
-                        }
-                    } else {
+                    let plugin_results =
+                        self.plugins.can_break_now(self.deferral());
+                    for plugin_result in &plugin_results {
                         println!(
-                            "There have been some errors from our plugins:"
+                            "plugin {} answered {} in {:?}",
+                            plugin_result.name,
+                            plugin_result.answer,
+                            plugin_result.duration
                         );
-                        for e in errs {
-                            println!("{}", e);
+                    }
+                    let blocking_plugins: Vec<String> = plugin_results
+                        .iter()
+                        .filter(|plugin_result| plugin_result.blocks_break)
+                        .map(|plugin_result| plugin_result.name.clone())
+                        .collect();
+                    let overridden_plugins: Vec<String> = plugin_results
+                        .iter()
+                        .filter(|plugin_result| {
+                            plugin_result.max_deferral_exceeded
+                        })
+                        .map(|plugin_result| plugin_result.name.clone())
+                        .collect();
+                    self.sender
+                        .send(super::Msg::PluginsChecked(plugin_results));
+
+                    if blocking_plugins.is_empty() {
+                        println!("Scheduler realized it was able to break, so sending a message.");
+                        if !overridden_plugins.is_empty() {
+                            self.sender.send(super::Msg::MaxDeferralExceeded(
+                                overridden_plugins,
+                            ));
                         }
-                        println!("Sleeping again just to be safe...");
+                        self.start_break(tier_index);
+                        return WaitUntilBreakResult::FinishedWaiting;
                     }
+                    self.break_blocked(tier_index, blocking_plugins);
                 }
                 WaitingResult::NeedToRestart => {
                     // Just let this loop restart.
                     println!(
-                        "Scheduler got a message to restart sleeping again, probably because X has been idle..."
+                        "Scheduler got a message to restart sleeping again, probably because X has been idle or the break was postponed..."
                     );
                 }
                 WaitingResult::Paused => {
+                    self.unblock_break();
                     return WaitUntilBreakResult::Paused;
                 }
+                WaitingResult::Stopped => {
+                    return WaitUntilBreakResult::Stopped;
+                }
+                WaitingResult::BreakNow => {
+                    // Breaks that have been asked for explicitly don't check
+                    // the plugins.
+                    if let Some(tier_index) = self.countdowns.next_tier() {
+                        println!(
+                            "Scheduler was asked to break now, so starting a break for {}",
+                            self.countdowns.tier(tier_index).name
+                        );
+                        self.start_break(tier_index);
+                        return WaitUntilBreakResult::FinishedWaiting;
+                    }
+                }
             }
         }
     }
 
+    /// Plugins have stopped the break for this tier from starting, so wait
+    /// before asking them again.
+    fn break_blocked(
+        &mut self,
+        tier_index: usize,
+        blocking_plugins: Vec<String>,
+    ) {
+        let recheck_seconds = self.config.settings.recheck_seconds_when_blocked;
+        if recheck_seconds == 0 {
+            println!("Could not break right now, so sleeping again...");
+            self.countdowns.restart(tier_index);
+        } else {
+            println!(
+                "Could not break right now, so checking again in {} seconds...",
+                recheck_seconds
+            );
+            self.countdowns
+                .delay(tier_index, Duration::from_secs(recheck_seconds.into()));
+        }
+        self.save_state();
+
+        let deferral = self.deferral();
+        let now = self.clock.now();
+        self.blocked_since.get_or_insert(now);
+        self.sender
+            .send(super::Msg::BreakBlocked(blocking_plugins, deferral));
+    }
+
+    /// How long plugins have been delaying the next break.
+    fn deferral(&self) -> Duration {
+        self.blocked_since.map_or_else(
+            || Duration::from_secs(0),
+            |since| self.clock.elapsed_since(since),
+        )
+    }
+
+    /// The next break is no longer blocked, for instance because it has
+    /// started, or because the countdown has restarted.  This tells the UI
+    /// that no plugins are blocking the break anymore.
+    fn unblock_break(&mut self) {
+        if self.blocked_since.take().is_some() {
+            self.sender
+                .send(super::Msg::BreakBlocked(vec![], Duration::from_secs(0)));
+        }
+    }
+
+    fn start_break(&mut self, tier_index: usize) {
+        self.postponed_time_until_break = None;
+        self.unblock_break();
+        self.current_break_tier = Some(tier_index);
+        let break_tier = self.countdowns.tier(tier_index).clone();
+        self.sender.send(super::Msg::StartBreak(break_tier));
+    }
+
+    #[allow(clippy::too_many_lines)]
     fn send_msgs_while_waiting(&mut self) -> WaitingResult {
-        self.sender.send(super::Msg::ResetSysTrayIcon).expect(
-            "TODO: figure out what to do about channels potentially failing",
-        );
-        let mut remaining_time = self.time_until_break;
-        for period in
-            create_periods_to_send_time_left_message(self.time_until_break)
+        self.sender.send(super::Msg::ResetSysTrayIcon);
+        let time_until_break = self
+            .postponed_time_until_break
+            .unwrap_or_else(|| self.countdowns.time_until_next_break());
+        let mut remaining_time = time_until_break;
+        for period in create_periods_to_send_time_left_message(time_until_break)
         {
             let opt_time_to_sleep = remaining_time.checked_sub(period);
             println!("In send_msgs_while_waiting loop for period {:?}, remaining_time: {:?}, time_to_sleep: {:?}", period, remaining_time, opt_time_to_sleep);
@@ -242,27 +453,78 @@ impl Scheduler {
                     // the remaining time.  We can just skip this.
                 }
                 Some(time_to_sleep) => {
-                    let res = self
-                        .restart_wait_time_receiver
-                        .recv_timeout(time_to_sleep);
+                    let sleep_start = self.clock.now();
+                    let res = self.events.recv_timeout(time_to_sleep);
                     match res {
-                        Ok(InnerMsg::HasBeenIdle) => {
+                        Ok(msg @ InnerMsg::HasBeenIdle)
+                        | Ok(msg @ InnerMsg::IdleEnded(_)) => {
                             println!(
-                                "\tIn send_msgs_while_waiting loop for period {:?}, remaining_time: {:?}, time_to_sleep: {:?}, got HasBeenIdle message",
-                                period, remaining_time, opt_time_to_sleep);
+                                "\tIn send_msgs_while_waiting loop for period {:?}, remaining_time: {:?}, time_to_sleep: {:?}, got {:?} message",
+                                period, remaining_time, opt_time_to_sleep, msg);
 
                             if self
                                 .idle_detection_enabled
                                 .load(Ordering::Relaxed)
                             {
+                                self.credit_idle_time(&msg);
                                 return WaitingResult::NeedToRestart;
                             }
                         }
+                        Ok(InnerMsg::ShortIdleEnded(idle_time)) => {
+                            println!("\tIn send_msgs_while_waiting loop for period {:?}, remaining_time: {:?}, time_to_sleep: {:?}, got ShortIdleEnded message",
+                                period, remaining_time, opt_time_to_sleep);
+                            // The time waited so far always counts, and only
+                            // the proportional mode gives extra credit for
+                            // the idle time.
+                            self.elapse(self.clock.elapsed_since(sleep_start));
+                            if self.config.settings.idle_credit_mode
+                                == IdleCreditMode::Proportional
+                                && self
+                                    .idle_detection_enabled
+                                    .load(Ordering::Relaxed)
+                            {
+                                self.credit_short_idle_time(idle_time);
+                            }
+                            return WaitingResult::NeedToRestart;
+                        }
+                        Ok(InnerMsg::LockedOrSuspended(away_time)) => {
+                            println!("\tIn send_msgs_while_waiting loop for period {:?}, remaining_time: {:?}, time_to_sleep: {:?}, got LockedOrSuspended message",
+                                period, remaining_time, opt_time_to_sleep);
+                            self.elapse(self.clock.elapsed_since(sleep_start));
+                            self.credit_away_time(away_time);
+                            return WaitingResult::NeedToRestart;
+                        }
                         Ok(InnerMsg::Pause) => {
                             println!("\tIn send_msgs_while_waiting loop for period {:?}, remaining_time: {:?}, time_to_sleep: {:?}, got Pause message",
                                 period, remaining_time, opt_time_to_sleep);
                             return WaitingResult::Paused;
                         }
+                        Ok(InnerMsg::Postpone(postpone_time)) => {
+                            println!("\tIn send_msgs_while_waiting loop for period {:?}, remaining_time: {:?}, time_to_sleep: {:?}, got Postpone message",
+                                period, remaining_time, opt_time_to_sleep);
+                            self.elapse(self.clock.elapsed_since(sleep_start));
+                            self.postponed_time_until_break =
+                                Some(postpone_time);
+                            return WaitingResult::NeedToRestart;
+                        }
+                        Ok(InnerMsg::BreakNow) => {
+                            println!("\tIn send_msgs_while_waiting loop for period {:?}, remaining_time: {:?}, time_to_sleep: {:?}, got BreakNow message",
+                                period, remaining_time, opt_time_to_sleep);
+                            return WaitingResult::BreakNow;
+                        }
+                        Ok(InnerMsg::Skip) => {
+                            println!("\tIn send_msgs_while_waiting loop for period {:?}, remaining_time: {:?}, time_to_sleep: {:?}, got Skip message",
+                                period, remaining_time, opt_time_to_sleep);
+                            self.skip_next_break();
+                            return WaitingResult::NeedToRestart;
+                        }
+                        Ok(InnerMsg::ReloadConfig(config)) => {
+                            println!("\tIn send_msgs_while_waiting loop for period {:?}, remaining_time: {:?}, time_to_sleep: {:?}, got ReloadConfig message",
+                                period, remaining_time, opt_time_to_sleep);
+                            self.elapse(self.clock.elapsed_since(sleep_start));
+                            self.reload_config(*config);
+                            return WaitingResult::NeedToRestart;
+                        }
                         Ok(InnerMsg::EnableIdleDetector) => {
                             println!("\tIn send_msgs_while_waiting loop for period {:?}, remaining_time: {:?}, time_to_sleep: {:?}, got EnableIdleDetector message",
                                 period, remaining_time, opt_time_to_sleep);
@@ -273,8 +535,9 @@ impl Scheduler {
                             // TODO: This doesn't logically belong here.
                             self.sender.send(
                                 super::Msg::TimeRemainingBeforeBreak(period),
-                            ).expect("TODO: figure out what to do about channels potentially failing");
+                            );
                             remaining_time -= time_to_sleep;
+                            self.elapse(time_to_sleep);
                         }
                         Ok(InnerMsg::DisableIdleDetector) => {
                             println!("\tIn send_msgs_while_waiting loop for period {:?}, remaining_time: {:?}, time_to_sleep: {:?}, got DisableIdleDetector message",
@@ -286,16 +549,21 @@ impl Scheduler {
                             // TODO: This doesn't logically belong here.
                             self.sender.send(
                                 super::Msg::TimeRemainingBeforeBreak(period),
-                            ).expect("TODO: figure out what to do about channels potentially failing");
+                            );
                             remaining_time -= time_to_sleep;
+                            self.elapse(time_to_sleep);
+                        }
+                        Err(RecvTimeoutError::Disconnected) => {
+                            return WaitingResult::Stopped;
                         }
-                        Err(_) => {
+                        Err(RecvTimeoutError::Timeout) => {
                             println!("\tIn send_msgs_while_waiting loop for period {:?}, remaining_time: {:?}, time_to_sleep: {:?}, timeout no message",
                                 period, remaining_time, opt_time_to_sleep);
                             self.sender.send(
                                 super::Msg::TimeRemainingBeforeBreak(period),
-                            ).expect("TODO: figure out what to do about channels potentially failing");
+                            );
                             remaining_time -= time_to_sleep;
+                            self.elapse(time_to_sleep);
                         }
                     }
                 }
@@ -304,19 +572,135 @@ impl Scheduler {
 
         WaitingResult::Finished
     }
+
+    /// The user has been idle, so restart the countdowns.
+    fn credit_idle_time(&mut self, msg: &InnerMsg) {
+        if let InnerMsg::IdleEnded(idle_time) = msg {
+            // The time spent idle counts as a break.
+            self.sender.send(super::Msg::IdleCredited(*idle_time));
+        }
+        self.postponed_time_until_break = None;
+        self.countdowns.restart_all();
+        self.save_state();
+        self.unblock_break();
+    }
+
+    /// The screen was locked or the computer was suspended, so count this as
+    /// a break for all the tiers with a break that isn't longer than that.
+    fn credit_away_time(&mut self, away_time: Duration) {
+        if self.countdowns.credit_break_time(away_time) {
+            self.sender.send(super::Msg::IdleCredited(away_time));
+            self.save_state();
+            self.unblock_break();
+        }
+    }
+
+    /// The user has been idle for a short time, so push the countdowns back
+    /// by part of the idle time.
+    fn credit_short_idle_time(&mut self, idle_time: Duration) {
+        let credit = idle_time * self.config.settings.idle_credit_percent / 100;
+        println!(
+            "idle for {:?}, pushing the countdowns back by {:?}",
+            idle_time, credit
+        );
+        self.countdowns.credit(credit);
+        self.save_state();
+    }
+
+    /// Restart the countdown for the next break as if it had been taken.
+    fn skip_next_break(&mut self) {
+        self.postponed_time_until_break = None;
+        self.unblock_break();
+        if let Some(tier_index) = self.countdowns.next_tier() {
+            self.countdowns.take_break(tier_index);
+            self.save_state();
+        }
+    }
+
+    /// Start using the settings from a config file that has been reloaded.
+    /// The countdowns keep going.
+    fn reload_config(&mut self, config: Config) {
+        let break_tiers = config.settings.break_tiers();
+        if break_tiers != self.config.settings.break_tiers() {
+            self.countdowns.set_tiers(break_tiers);
+            self.save_state();
+        }
+
+        if config.settings.all_plugin_settings
+            != self.config.settings.all_plugin_settings
+        {
+            match Plugins::new(&config) {
+                Err(()) => {
+                    println!("Could not initialize plugins with the new settings, so keeping the old plugins");
+                }
+                Ok(plugins) => self.plugins = plugins,
+            }
+        }
+
+        self.idle_detection_time
+            .set(config.settings.idle_detection_seconds);
+
+        self.config = config;
+    }
+
+    fn elapse(&mut self, time: Duration) {
+        self.countdowns.elapse(time);
+        self.postponed_time_until_break = self
+            .postponed_time_until_break
+            .map(|postponed| postponed.checked_sub(time).unwrap_or_default());
+        // This happens every time a time-left message is sent, so only save
+        // once in a while.
+        if self.clock.elapsed_since(self.last_state_save) >= SAVE_STATE_INTERVAL
+        {
+            self.save_state();
+        }
+    }
+
+    fn save_state(&mut self) {
+        self.last_state_save = self.clock.now();
+        let saved_state = SavedState {
+            saved_at: saved_state::unix_time_now(),
+            last_break_end: self.last_break_end,
+            remaining_seconds: self.countdowns.remaining_seconds(),
+        };
+        if let Err(err) = saved_state.save(&self.config.cache_dir) {
+            println!("Could not save the scheduler state: {}", err);
+        }
+    }
 }
 
 enum WaitingResult {
     Finished,
     NeedToRestart,
     Paused,
+    BreakNow,
+    /// There will never be any more messages from `events`.
+    Stopped,
 }
 
+#[derive(Debug)]
 pub enum InnerMsg {
     Pause,
+    Postpone(Duration),
+    /// The user has been idle for `idle_detection_seconds`.
     HasBeenIdle,
+    /// The user has become active again after being idle for the given
+    /// amount of time.
+    IdleEnded(Duration),
+    /// The user has become active again after being idle for the given
+    /// amount of time, which is too short to count as a break.
+    ShortIdleEnded(Duration),
+    /// The screen was locked or the computer was suspended for the given
+    /// amount of time.
+    LockedOrSuspended(Duration),
+    /// Start a break right now, without waiting for the countdown to finish.
+    BreakNow,
+    /// Skip the next break, restarting the countdown as if it had been taken.
+    Skip,
     EnableIdleDetector,
     DisableIdleDetector,
+    /// The config file has been reloaded.
+    ReloadConfig(Box<Config>),
 }
 
 fn create_periods_to_send_time_left_message(
@@ -340,6 +724,301 @@ fn create_periods_to_send_time_left_message(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::Settings;
+    use plugins::{CanBreak, Plugin, PolicySettings};
+    use simulation::{SimulatedClock, SimulatedEvents, SimulatedOutbox};
+    use std::path::PathBuf;
+    use std::sync::atomic::AtomicU32;
+
+    /// A plugin that blocks the first few breaks it is asked about.
+    struct Blocker {
+        blocks_remaining: AtomicU32,
+    }
+
+    impl Plugin for Blocker {
+        fn can_break_now(
+            &self,
+        ) -> Result<CanBreak, Box<dyn std::error::Error>> {
+            let blocks_remaining = self.blocks_remaining.load(Ordering::SeqCst);
+            if blocks_remaining == 0 {
+                Ok(CanBreak::Yes)
+            } else {
+                self.blocks_remaining
+                    .store(blocks_remaining - 1, Ordering::SeqCst);
+                Ok(CanBreak::No)
+            }
+        }
+
+        fn name(&self) -> String {
+            String::from("blocker")
+        }
+    }
+
+    const fn mins(mins: u64) -> Duration {
+        Duration::from_secs(mins * 60)
+    }
+
+    /// The config for simulations, with breaks every hour.
+    fn test_config(test_name: &str) -> Config {
+        Config {
+            file_path: PathBuf::from("config.toml"),
+            cache_dir: std::env::temp_dir().join(format!(
+                "break-time-test-{}-{}",
+                test_name,
+                std::process::id()
+            )),
+            settings: Settings {
+                seconds_between_breaks: 60 * 60,
+                break_duration_seconds: 10 * 60,
+                idle_detection_seconds: 8 * 60,
+                recheck_seconds_when_blocked: 60,
+                ..Settings::default()
+            },
+        }
+    }
+
+    /// Run the scheduler with a simulated clock until `end`, with
+    /// `test_config`.  This returns all the messages the scheduler sent, with
+    /// the simulated time they were sent at.
+    fn simulate(
+        test_name: &str,
+        plugins: Vec<Arc<dyn Plugin>>,
+        end: Duration,
+        add_events: impl FnOnce(SimulatedEvents) -> SimulatedEvents,
+    ) -> Vec<(Duration, super::super::Msg)> {
+        simulate_with_policy(
+            test_name,
+            plugins,
+            &PolicySettings::default(),
+            end,
+            add_events,
+        )
+    }
+
+    /// Like `simulate`, but with `policy` for all the plugins.
+    fn simulate_with_policy(
+        test_name: &str,
+        plugins: Vec<Arc<dyn Plugin>>,
+        policy: &PolicySettings,
+        end: Duration,
+        add_events: impl FnOnce(SimulatedEvents) -> SimulatedEvents,
+    ) -> Vec<(Duration, super::super::Msg)> {
+        let config = test_config(test_name);
+        let cache_dir = config.cache_dir.clone();
+        std::fs::create_dir_all(&cache_dir).unwrap();
+        let msgs = run_simulation(&config, plugins, policy, end, add_events);
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+        msgs
+    }
+
+    /// Run the scheduler with `config`, which needs to have an existing
+    /// `cache_dir`.
+    fn run_simulation(
+        config: &Config,
+        plugins: Vec<Arc<dyn Plugin>>,
+        policy: &PolicySettings,
+        end: Duration,
+        add_events: impl FnOnce(SimulatedEvents) -> SimulatedEvents,
+    ) -> Vec<(Duration, super::super::Msg)> {
+        let plugins = Plugins(
+            plugins
+                .into_iter()
+                .map(|plugin| {
+                    PluginRunner::new(
+                        plugin,
+                        policy.resolve(&PolicySettings::default()),
+                    )
+                })
+                .collect(),
+        );
+
+        let clock = SimulatedClock::new();
+        let outbox = SimulatedOutbox::new(&clock);
+        let mut sched = Scheduler::new(
+            config,
+            plugins,
+            Arc::new(AtomicBool::new(true)),
+            Arc::new(IdleDetectionTime::new(
+                config.settings.idle_detection_seconds,
+            )),
+            clock.clone(),
+            add_events(SimulatedEvents::new(&clock, end)),
+            outbox.clone(),
+        );
+        sched.run_loop();
+
+        assert_eq!(clock.elapsed(), end);
+        outbox.msgs()
+    }
+
+    fn break_start_times(
+        msgs: &[(Duration, super::super::Msg)],
+    ) -> Vec<Duration> {
+        msgs.iter()
+            .filter(|(_, msg)| matches!(msg, super::super::Msg::StartBreak(_)))
+            .map(|(time, _)| *time)
+            .collect()
+    }
+
+    #[test]
+    fn test_simulated_breaks() {
+        let msgs = simulate("breaks", vec![], mins(180), |events| {
+            events
+                .break_ending(mins(10), Msg::Start)
+                .break_ending(mins(10), Msg::Start)
+        });
+        // The countdown to the next break only starts once a break has ended.
+        assert_eq!(break_start_times(&msgs), vec![mins(60), mins(130)]);
+    }
+
+    #[test]
+    fn test_simulated_idle_resets_countdown() {
+        let msgs = simulate("idle", vec![], mins(120), |events| {
+            events
+                .at(mins(30), InnerMsg::HasBeenIdle)
+                .at(mins(40), InnerMsg::IdleEnded(mins(18)))
+                .break_ending(mins(10), Msg::Start)
+        });
+        assert_eq!(break_start_times(&msgs), vec![mins(100)]);
+        assert!(msgs.iter().any(|(time, msg)| *time == mins(40)
+            && matches!(msg, super::super::Msg::IdleCredited(idle_time) if *idle_time == mins(18))));
+    }
+
+    #[test]
+    fn test_simulated_short_idle_in_reset_mode() {
+        let secs = Duration::from_secs;
+        let msgs = simulate("short-idle", vec![], mins(90), |events| {
+            events
+                .at(mins(20) + secs(30), InnerMsg::ShortIdleEnded(mins(3)))
+                .at(mins(40) + secs(15), InnerMsg::ShortIdleEnded(mins(5)))
+                .break_ending(mins(10), Msg::Start)
+        });
+        // Short idle times count for nothing in the default `reset` mode, but
+        // they don't lose any of the countdown either.
+        assert_eq!(break_start_times(&msgs), vec![mins(60)]);
+        let time_remaining: Vec<Duration> = msgs
+            .iter()
+            .filter_map(|(time, msg)| match msg {
+                super::super::Msg::TimeRemainingBeforeBreak(_) => Some(*time),
+                _ => None,
+            })
+            .filter(|time| *time > mins(20) && *time <= mins(22))
+            .collect();
+        assert_eq!(time_remaining, vec![mins(21), mins(22)]);
+    }
+
+    #[test]
+    fn test_simulated_pause() {
+        let msgs = simulate("pause", vec![], mins(120), |events| {
+            events
+                .at(mins(20), InnerMsg::Pause)
+                .break_ending(mins(30), Msg::Start)
+        });
+        // No break happens while paused, and the countdown restarts once
+        // break-time is resumed.
+        assert_eq!(break_start_times(&msgs), vec![mins(110)]);
+    }
+
+    #[test]
+    fn test_simulated_reload_config() {
+        let reload = || InnerMsg::ReloadConfig(Box::new(test_config("reload")));
+        let msgs = simulate("reload", vec![], mins(100), |events| {
+            events
+                .at(mins(30) + Duration::from_secs(30), reload())
+                .at(mins(72), reload())
+                .break_ending(mins(10), Msg::Postpone(mins(5)))
+                .break_ending(mins(10), Msg::Start)
+        });
+        // Reloading the config neither loses the time waited so far, nor
+        // ends the time the break was postponed for.
+        assert_eq!(break_start_times(&msgs), vec![mins(60), mins(75)]);
+    }
+
+    #[test]
+    fn test_simulated_plugin_blocks() {
+        let blocker = Blocker {
+            blocks_remaining: AtomicU32::new(3),
+        };
+        let msgs =
+            simulate("blocks", vec![Arc::new(blocker)], mins(90), |events| {
+                events.break_ending(mins(10), Msg::Start)
+            });
+        assert_eq!(break_start_times(&msgs), vec![mins(63)]);
+
+        // The plugin is asked again every minute until it stops blocking.
+        let blocked_msgs: Vec<(Duration, Vec<String>, Duration)> = msgs
+            .into_iter()
+            .filter_map(|(time, msg)| match msg {
+                super::super::Msg::BreakBlocked(plugins, deferral) => {
+                    Some((time, plugins, deferral))
+                }
+                _ => None,
+            })
+            .collect();
+        let blocker = || vec![String::from("blocker")];
+        assert_eq!(
+            blocked_msgs,
+            vec![
+                (mins(60), blocker(), mins(0)),
+                (mins(61), blocker(), mins(1)),
+                (mins(62), blocker(), mins(2)),
+                (mins(63), vec![], mins(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_simulated_max_deferral() {
+        let blocker = Blocker {
+            blocks_remaining: AtomicU32::new(100),
+        };
+        let policy = PolicySettings {
+            max_deferral_seconds: Some(3 * 60),
+            ..PolicySettings::default()
+        };
+        let msgs = simulate_with_policy(
+            "max-deferral",
+            vec![Arc::new(blocker)],
+            &policy,
+            mins(70),
+            |events| events,
+        );
+        assert_eq!(break_start_times(&msgs), vec![mins(63)]);
+
+        // The user is only told about the break starting anyway once, when
+        // it actually starts.
+        let exceeded: Vec<(Duration, Vec<String>)> = msgs
+            .into_iter()
+            .filter_map(|(time, msg)| match msg {
+                super::super::Msg::MaxDeferralExceeded(plugins) => {
+                    Some((time, plugins))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(exceeded, vec![(mins(63), vec![String::from("blocker")])]);
+    }
+
+    #[test]
+    fn test_simulated_save_state_interval() {
+        let config = test_config("save-state-interval");
+        std::fs::create_dir_all(&config.cache_dir).unwrap();
+        run_simulation(
+            &config,
+            vec![],
+            &PolicySettings::default(),
+            mins(14),
+            |events| events,
+        );
+        let saved_state = SavedState::load(&config.cache_dir).unwrap();
+        std::fs::remove_dir_all(&config.cache_dir).unwrap();
+
+        // The countdown was last saved 10 minutes in, and not at every
+        // time-left message after that.
+        let remaining_seconds: Vec<u64> =
+            saved_state.remaining_seconds.values().copied().collect();
+        assert_eq!(remaining_seconds, vec![50 * 60]);
+    }
 
     #[test]
     fn test_periods_to_send_time_left_message() {
@@ -0,0 +1,339 @@
+// This module keeps track of the countdown for each kind of break (each `BreakTier`).
+//
+// The Scheduler waits until the countdown of one of the tiers finishes, and then asks this module
+// which break should actually be taken.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::config::BreakTier;
+
+/// If the countdown for a tier is within this amount of time from finishing
+/// when another tier's countdown finishes, then both tiers are considered to
+/// be due together.
+///
+/// This stops a short break from happening right before a long break.
+const DUE_TOGETHER_SECONDS: u64 = 60;
+
+pub struct Countdowns {
+    tiers: Vec<BreakTier>,
+    remaining: Vec<Duration>,
+}
+
+impl Countdowns {
+    pub fn new(tiers: Vec<BreakTier>) -> Self {
+        let remaining = tiers.iter().map(time_between_breaks).collect();
+        Self { tiers, remaining }
+    }
+
+    pub fn tier(&self, tier_index: usize) -> &BreakTier {
+        &self.tiers[tier_index]
+    }
+
+    /// The amount of time until the countdown for the next tier finishes.
+    pub fn time_until_next_break(&self) -> Duration {
+        self.remaining
+            .iter()
+            .min()
+            .copied()
+            .unwrap_or_else(|| Duration::from_secs(0))
+    }
+
+    /// Let some time pass for all the countdowns.
+    pub fn elapse(&mut self, time: Duration) {
+        for remaining in &mut self.remaining {
+            *remaining = remaining.checked_sub(time).unwrap_or_default();
+        }
+    }
+
+    /// Figure out which tier should have a break now.
+    ///
+    /// This returns `None` if none of the countdowns have finished.  If
+    /// multiple tiers are due together, the tier with the longest break wins.
+    pub fn due_tier(&self) -> Option<usize> {
+        if self.time_until_next_break() > Duration::from_secs(0) {
+            return None;
+        }
+        self.next_tier()
+    }
+
+    /// Figure out which tier will have the next break.
+    ///
+    /// If multiple tiers will be due together, the tier with the longest
+    /// break wins.
+    pub fn next_tier(&self) -> Option<usize> {
+        let due_together = self.time_until_next_break()
+            + Duration::from_secs(DUE_TOGETHER_SECONDS);
+
+        self.remaining
+            .iter()
+            .enumerate()
+            .filter(|(_, remaining)| **remaining <= due_together)
+            .map(|(tier_index, _)| tier_index)
+            .max_by_key(|tier_index| {
+                self.tiers[*tier_index].break_duration_seconds
+            })
+    }
+
+    /// A break for the given tier has been taken.
+    ///
+    /// This restarts the countdown for this tier, as well as for all the tiers
+    /// with a shorter (or equal) break.
+    pub fn take_break(&mut self, tier_index: usize) {
+        let break_duration = self.tiers[tier_index].break_duration_seconds;
+        for (tier, remaining) in self.tiers.iter().zip(&mut self.remaining) {
+            if tier.break_duration_seconds <= break_duration {
+                *remaining = time_between_breaks(tier);
+            }
+        }
+    }
+
+    /// Restart the countdown for a single tier, without affecting any of the
+    /// other tiers.
+    pub fn restart(&mut self, tier_index: usize) {
+        self.remaining[tier_index] =
+            time_between_breaks(&self.tiers[tier_index]);
+    }
+
+    /// Count `time` as a break, for instance because the screen was locked.
+    ///
+    /// The countdown restarts for each tier with a break that isn't longer
+    /// than `time`.  This returns whether any of the countdowns restarted.
+    pub fn credit_break_time(&mut self, time: Duration) -> bool {
+        let mut credited = false;
+        for (tier, remaining) in self.tiers.iter().zip(&mut self.remaining) {
+            let break_duration =
+                Duration::from_secs(tier.break_duration_seconds.into());
+            if break_duration <= time {
+                *remaining = time_between_breaks(tier);
+                credited = true;
+            }
+        }
+        credited
+    }
+
+    /// Push all the countdowns back by `time`, for instance because the user
+    /// has been idle for a short time.  A countdown is never pushed back
+    /// further than the full time between breaks.
+    pub fn credit(&mut self, time: Duration) {
+        for (tier, remaining) in self.tiers.iter().zip(&mut self.remaining) {
+            *remaining =
+                std::cmp::min(*remaining + time, time_between_breaks(tier));
+        }
+    }
+
+    /// Set the countdown for a single tier to finish after `time`, for
+    /// instance to check again soon whether a blocked break can happen.
+    pub fn delay(&mut self, tier_index: usize, time: Duration) {
+        self.remaining[tier_index] = time;
+    }
+
+    /// The number of seconds remaining for each tier, keyed by the tier name.
+    pub fn remaining_seconds(&self) -> HashMap<String, u64> {
+        self.tiers
+            .iter()
+            .zip(&self.remaining)
+            .map(|(tier, remaining)| (tier.name.clone(), remaining.as_secs()))
+            .collect()
+    }
+
+    /// Resume the countdowns from a previous run of break-time.
+    ///
+    /// `time_off` is the amount of time break-time was not running.  If this
+    /// is longer than the break for a tier, then it counts as a break, and
+    /// the countdown for that tier starts from the beginning.
+    pub fn resume(
+        &mut self,
+        remaining_seconds: &HashMap<String, u64>,
+        time_off: Duration,
+    ) {
+        for (tier, remaining) in self.tiers.iter().zip(&mut self.remaining) {
+            let break_duration =
+                Duration::from_secs(tier.break_duration_seconds.into());
+            if time_off >= break_duration {
+                continue;
+            }
+            if let Some(saved_remaining) = remaining_seconds.get(&tier.name) {
+                *remaining = std::cmp::min(
+                    *remaining,
+                    Duration::from_secs(*saved_remaining),
+                );
+            }
+        }
+    }
+
+    /// Change the tiers, for instance because the config file was reloaded.
+    ///
+    /// Tiers that are kept (going by their name) keep counting down, although
+    /// the countdown is shortened if the new time between breaks is shorter.
+    /// New tiers start counting down from the beginning.
+    pub fn set_tiers(&mut self, tiers: Vec<BreakTier>) {
+        let remaining_seconds = self.remaining_seconds();
+        *self = Self::new(tiers);
+        self.resume(&remaining_seconds, Duration::from_secs(0));
+    }
+
+    /// Restart the countdowns for all tiers.
+    pub fn restart_all(&mut self) {
+        for (tier, remaining) in self.tiers.iter().zip(&mut self.remaining) {
+            *remaining = time_between_breaks(tier);
+        }
+    }
+}
+
+fn time_between_breaks(tier: &BreakTier) -> Duration {
+    Duration::from_secs(tier.seconds_between_breaks.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tier(
+        break_duration_seconds: u32,
+        seconds_between_breaks: u32,
+    ) -> BreakTier {
+        BreakTier {
+            name: format!("{}s break", break_duration_seconds),
+            break_duration_seconds,
+            seconds_between_breaks,
+            clicks_to_end_break_early: 10,
+            message: String::from("break"),
+        }
+    }
+
+    #[test]
+    fn test_shorter_tier_is_due_first() {
+        let mut countdowns =
+            Countdowns::new(vec![tier(600, 60 * 60), tier(20, 20 * 60)]);
+
+        assert_eq!(
+            countdowns.time_until_next_break(),
+            Duration::from_secs(20 * 60)
+        );
+        assert_eq!(countdowns.due_tier(), None);
+
+        countdowns.elapse(Duration::from_secs(20 * 60));
+        assert_eq!(countdowns.due_tier(), Some(1));
+
+        countdowns.take_break(1);
+        assert_eq!(
+            countdowns.time_until_next_break(),
+            Duration::from_secs(20 * 60)
+        );
+    }
+
+    #[test]
+    fn test_longer_tier_wins_when_due_together() {
+        let mut countdowns =
+            Countdowns::new(vec![tier(20, 20 * 60), tier(600, 60 * 60)]);
+
+        for _ in 0..2 {
+            countdowns.elapse(Duration::from_secs(20 * 60));
+            let due_tier = countdowns.due_tier().unwrap();
+            assert_eq!(due_tier, 0);
+            countdowns.take_break(due_tier);
+        }
+
+        countdowns.elapse(Duration::from_secs(20 * 60));
+        assert_eq!(countdowns.due_tier(), Some(1));
+
+        // Taking the long break also restarts the countdown for the short break.
+        countdowns.take_break(1);
+        assert_eq!(
+            countdowns.time_until_next_break(),
+            Duration::from_secs(20 * 60)
+        );
+    }
+
+    #[test]
+    fn test_credit_break_time() {
+        let mut countdowns =
+            Countdowns::new(vec![tier(20, 20 * 60), tier(600, 60 * 60)]);
+        countdowns.elapse(Duration::from_secs(15 * 60));
+
+        assert!(!countdowns.credit_break_time(Duration::from_secs(10)));
+
+        // The screen was locked for 5 minutes.  This counts as a short break,
+        // but not as a long break.
+        assert!(countdowns.credit_break_time(Duration::from_secs(5 * 60)));
+
+        let mut expected = HashMap::new();
+        expected.insert(String::from("20s break"), 20 * 60);
+        expected.insert(String::from("600s break"), 45 * 60);
+        assert_eq!(countdowns.remaining_seconds(), expected);
+    }
+
+    #[test]
+    fn test_credit() {
+        let mut countdowns =
+            Countdowns::new(vec![tier(20, 20 * 60), tier(600, 60 * 60)]);
+        countdowns.elapse(Duration::from_secs(30 * 60));
+        countdowns.take_break(0);
+        countdowns.elapse(Duration::from_secs(10 * 60));
+
+        countdowns.credit(Duration::from_secs(15 * 60));
+
+        let mut expected = HashMap::new();
+        expected.insert(String::from("20s break"), 20 * 60);
+        expected.insert(String::from("600s break"), 35 * 60);
+        assert_eq!(countdowns.remaining_seconds(), expected);
+    }
+
+    #[test]
+    fn test_delay() {
+        let mut countdowns =
+            Countdowns::new(vec![tier(20, 20 * 60), tier(600, 60 * 60)]);
+        countdowns.elapse(Duration::from_secs(20 * 60));
+        assert_eq!(countdowns.due_tier(), Some(0));
+
+        // The break was blocked, so check again in a minute.
+        countdowns.delay(0, Duration::from_secs(60));
+        assert_eq!(countdowns.due_tier(), None);
+        assert_eq!(countdowns.time_until_next_break(), Duration::from_secs(60));
+
+        countdowns.elapse(Duration::from_secs(60));
+        assert_eq!(countdowns.due_tier(), Some(0));
+        assert_eq!(countdowns.remaining_seconds()["600s break"], 39 * 60);
+    }
+
+    #[test]
+    fn test_resume() {
+        let mut countdowns =
+            Countdowns::new(vec![tier(20, 20 * 60), tier(600, 60 * 60)]);
+        countdowns.elapse(Duration::from_secs(15 * 60));
+        let remaining_seconds = countdowns.remaining_seconds();
+
+        // The computer was off for 5 minutes.  This counts as a short break, but not as a long
+        // break.
+        let mut resumed =
+            Countdowns::new(vec![tier(20, 20 * 60), tier(600, 60 * 60)]);
+        resumed.resume(&remaining_seconds, Duration::from_secs(5 * 60));
+
+        let mut expected = HashMap::new();
+        expected.insert(String::from("20s break"), 20 * 60);
+        expected.insert(String::from("600s break"), 45 * 60);
+        assert_eq!(resumed.remaining_seconds(), expected);
+    }
+
+    #[test]
+    fn test_set_tiers() {
+        let mut countdowns =
+            Countdowns::new(vec![tier(20, 20 * 60), tier(600, 60 * 60)]);
+        countdowns.elapse(Duration::from_secs(15 * 60));
+
+        // The long break now happens every 30 minutes, and there is a new
+        // tier.
+        countdowns.set_tiers(vec![
+            tier(20, 20 * 60),
+            tier(600, 30 * 60),
+            tier(60, 40 * 60),
+        ]);
+
+        let mut expected = HashMap::new();
+        expected.insert(String::from("20s break"), 5 * 60);
+        expected.insert(String::from("600s break"), 30 * 60);
+        expected.insert(String::from("60s break"), 40 * 60);
+        assert_eq!(countdowns.remaining_seconds(), expected);
+    }
+}
@@ -0,0 +1,76 @@
+// This module defines how the scheduler talks to the outside world: a clock to tell the time, an
+// event source to wait for messages on, and an outbox to send messages to the rest of break-time.
+//
+// break-time itself uses the real clock and channels.  The tests use a simulated clock and event
+// source instead (see the simulation module), where time only passes when the scheduler waits for
+// an event.  This makes it possible to run hours of scheduling in a few milliseconds.
+
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use super::{InnerMsg, Msg};
+
+pub trait Clock {
+    fn now(&self) -> Instant;
+
+    fn elapsed_since(&self, earlier: Instant) -> Duration {
+        self.now().saturating_duration_since(earlier)
+    }
+}
+
+/// The real clock.  This doesn't include the time the computer was suspended,
+/// since that is handled by logind.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+pub trait EventSource {
+    /// Wait for up to `timeout` for a message while counting down to the next
+    /// break.  This returns `RecvTimeoutError::Disconnected` once there will
+    /// never be any more messages.
+    fn recv_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<InnerMsg, RecvTimeoutError>;
+
+    /// Wait for a break or a pause to end.  This returns `None` once there
+    /// will never be any more messages.
+    fn recv_break_ending(&mut self) -> Option<Msg>;
+}
+
+/// The channels the rest of break-time uses to send messages to the
+/// scheduler.
+pub struct Channels {
+    pub break_ending_receiver: Receiver<Msg>,
+    pub restart_wait_time_receiver: Receiver<InnerMsg>,
+}
+
+impl EventSource for Channels {
+    fn recv_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<InnerMsg, RecvTimeoutError> {
+        self.restart_wait_time_receiver.recv_timeout(timeout)
+    }
+
+    fn recv_break_ending(&mut self) -> Option<Msg> {
+        self.break_ending_receiver.recv().ok()
+    }
+}
+
+pub trait Outbox {
+    fn send(&self, msg: crate::Msg);
+}
+
+impl Outbox for glib::Sender<crate::Msg> {
+    fn send(&self, msg: crate::Msg) {
+        Self::send(self, msg).expect(
+            "TODO: figure out what to do about channels potentially failing",
+        );
+    }
+}
@@ -3,9 +3,11 @@
 //
 // This is used to reset the break timer whenever the user has stepped away from the computer.
 //
-// This currently uses a simple loop while querying the screensaver info, but really we should be
-// using a callback-based approach while listening to the XServer's XSync IDLETIME counter.  Here
-// are a few examples of using this:
+// There are two ways of detecting idleness.  The preferred way is to listen to the X Server's XSync
+// IDLETIME counter (see the xsync module).  This is event-driven, so the X Server tells us when the
+// user becomes idle and when they become active again.  If the X Server doesn't have the SYNC
+// extension, we fall back to a simple loop while querying the screensaver info.  Here are a few
+// examples of using the XSync IDLETIME counter:
 //
 // - https://chromium.googlesource.com/chromiumos/platform/power_manager/+/refs/heads/0.12.433.B62/xidle.h
 // - https://chromium.googlesource.com/chromiumos/platform/power_manager/+/refs/heads/0.12.433.B62/xidle.cc
@@ -14,18 +16,79 @@
 // - https://www.x.org/releases/X11R7.7/doc/xextproto/sync.html
 // - https://github.com/freedesktop/xorg-xserver/blob/7f962c70b6d9c346477f23f6c15211e749110078/Xext/sync.c
 
-use std::sync::atomic::{AtomicBool, Ordering};
+mod xsync;
+
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::mpsc::Sender;
-use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use super::InnerMsg;
-use crate::config::Config;
+use crate::clock::BootInstant;
 use crate::prelude::*;
+use xsync::XSyncIdleDetector;
 
 const SLEEP_SECONDS: u64 = 20;
 const SLEEP_MILLISECONDS: u128 = (SLEEP_SECONDS as u128) * 1000;
 
+/// Idle times shorter than this aren't reported at all, since not touching the
+/// keyboard or mouse for a few seconds (for instance, while reading) doesn't
+/// help as a break.
+const SHORT_IDLE_MILLISECONDS: u32 = 60 * 1000;
+
+/// The amount of time the user has to be idle before it counts as a break.
+///
+/// This is shared between the idle detector and the scheduler, so that it can
+/// be changed when the config file is reloaded.
+pub struct IdleDetectionTime {
+    milliseconds: AtomicU32,
+    /// The `XSyncIdleDetector`, if it is being used.  Its alarms have to be
+    /// changed whenever the idle detection time changes.
+    xsync_idle_detector: Mutex<Option<Arc<XSyncIdleDetector>>>,
+}
+
+impl IdleDetectionTime {
+    pub fn new(idle_detection_seconds: u32) -> Self {
+        Self {
+            milliseconds: AtomicU32::new(idle_detection_seconds * 1000),
+            xsync_idle_detector: Mutex::new(None),
+        }
+    }
+
+    pub fn milliseconds(&self) -> u32 {
+        self.milliseconds.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, idle_detection_seconds: u32) {
+        let opt_xsync_idle_detector = self
+            .xsync_idle_detector
+            .lock()
+            .expect("idle detector mutex poisoned");
+        self.milliseconds
+            .store(idle_detection_seconds * 1000, Ordering::Relaxed);
+        if let Some(xsync_idle_detector) = &*opt_xsync_idle_detector {
+            xsync_idle_detector.set_idle_detection_time(self.milliseconds());
+        }
+    }
+
+    fn set_xsync_idle_detector(
+        &self,
+        opt_xsync_idle_detector: Option<Arc<XSyncIdleDetector>>,
+    ) {
+        let mut xsync_idle_detector = self
+            .xsync_idle_detector
+            .lock()
+            .expect("idle detector mutex poisoned");
+        // The idle detection time may have changed while the XSync idle
+        // detector was being set up.
+        if let Some(new_xsync_idle_detector) = &opt_xsync_idle_detector {
+            new_xsync_idle_detector
+                .set_idle_detection_time(self.milliseconds());
+        }
+        *xsync_idle_detector = opt_xsync_idle_detector;
+    }
+}
+
 pub struct IdleDetector {
     conn: xcb::Connection,
     root_window: xcb::Window,
@@ -50,26 +113,61 @@ impl IdleDetector {
     }
 
     pub fn run(
-        config: &Config,
+        idle_detection_time: &Arc<IdleDetectionTime>,
+        idle_detection_enabled: &Arc<AtomicBool>,
+        restart_wait_time_sender: Sender<InnerMsg>,
+    ) -> ! {
+        match XSyncIdleDetector::new(idle_detection_time.milliseconds()) {
+            Ok(xsync_idle_detector) => {
+                println!("idle detector: using the XSync IDLETIME counter");
+                let xsync_idle_detector = Arc::new(xsync_idle_detector);
+                idle_detection_time
+                    .set_xsync_idle_detector(Some(xsync_idle_detector.clone()));
+                // This only returns if something has gone wrong with the
+                // connection to X, in which case we fall back to polling.
+                let _ = xsync_idle_detector.run(
+                    idle_detection_time,
+                    idle_detection_enabled,
+                    &restart_wait_time_sender,
+                );
+                idle_detection_time.set_xsync_idle_detector(None);
+            }
+            Err(()) => {
+                println!("idle detector: XSync IDLETIME counter not available, falling back to polling the screensaver info");
+            }
+        }
+
+        Self::run_poller(
+            idle_detection_time,
+            idle_detection_enabled,
+            restart_wait_time_sender,
+        )
+    }
+
+    fn run_poller(
+        idle_detection_time: &IdleDetectionTime,
         idle_detection_enabled: &Arc<AtomicBool>,
         restart_wait_time_sender: Sender<InnerMsg>,
     ) -> ! {
         let idle_detector = Self::new(restart_wait_time_sender);
-        let idle_detection_milliseconds =
-            config.settings.idle_detection_seconds * 1000;
+
+        // The time when the user became idle, if they are currently idle.
+        let mut opt_idle_start: Option<BootInstant> = None;
+        // The time since user input from the last time we checked.
+        let mut last_ms_since_user_input: u32 = 0;
+
         loop {
-            let time_before_sleep = SystemTime::now();
+            let time_before_sleep = BootInstant::now();
 
             let sleep_time_duration = Duration::from_secs(SLEEP_SECONDS);
             std::thread::sleep(sleep_time_duration);
 
             // Calculate the actual amount of time that has passed during sleep.
             // This will potentially be different from the sleep time because the computer could be
-            // suspended during the above std::thread::sleep().
-            let time_difference_milliseconds: u128 = time_before_sleep
-                .elapsed()
-                .unwrap_or(sleep_time_duration)
-                .as_millis();
+            // suspended during the above std::thread::sleep().  BootInstant keeps counting while
+            // the computer is suspended, but isn't affected by changes to the wall clock.
+            let time_difference_milliseconds: u128 =
+                time_before_sleep.elapsed().as_millis();
 
             // We subtract out the sleep time to get just the amount that the computer would have
             // been suspended for.  If the computer wasn't actually suspended, then this should be
@@ -85,6 +183,8 @@ impl IdleDetector {
             .unwrap();
 
             let ms_since_user_input = idle_query_res.ms_since_user_input();
+            let idle_detection_milliseconds =
+                idle_detection_time.milliseconds();
 
             println!(
                 "idle detector: ms_since_user_input: {}, suspend_milliseconds: {}, idle_detection_milliseconds: {}",
@@ -93,11 +193,45 @@ impl IdleDetector {
                 idle_detection_milliseconds,
             );
 
+            if let Some(short_idle_time) = short_idle_ended(
+                idle_detection_milliseconds,
+                last_ms_since_user_input,
+                ms_since_user_input,
+            ) {
+                if opt_idle_start.is_none()
+                    && idle_detection_enabled.load(Ordering::Relaxed)
+                {
+                    println!(
+                        "idle detector detected that we are no longer idle after a short idle time, so sending ShortIdleEnded message",
+                    );
+                    idle_detector
+                        .restart_wait_time_sender
+                        .send(InnerMsg::ShortIdleEnded(short_idle_time)).expect("TODO: figure out what to do about channels potentially failing");
+                }
+            }
+            last_ms_since_user_input = ms_since_user_input;
+
+            let now = BootInstant::now();
+            let last_user_input = now
+                .checked_sub(Duration::from_millis(ms_since_user_input.into()))
+                .unwrap_or(now);
+
             if has_been_idle(
                 idle_detection_milliseconds.into(),
                 ms_since_user_input.into(),
                 suspend_milliseconds,
             ) {
+                if opt_idle_start.is_none() {
+                    let suspend_time = Duration::from_millis(
+                        u64::try_from(suspend_milliseconds).unwrap_or(u64::MAX),
+                    );
+                    opt_idle_start = Some(
+                        last_user_input
+                            .checked_sub(suspend_time)
+                            .unwrap_or(last_user_input),
+                    );
+                }
+
                 if idle_detection_enabled.load(Ordering::Relaxed) {
                     println!(
                         "idle detector detected that we have been idle, so sending HasBeenIdle message",
@@ -110,6 +244,16 @@ impl IdleDetector {
                         "idle detector detected that we have been idle, but idle_detection is not enable, so not sending HasBeenIdle message",
                     );
                 }
+            } else if let Some(idle_start) = opt_idle_start.take() {
+                let idle_time = last_user_input.duration_since(idle_start);
+                if idle_detection_enabled.load(Ordering::Relaxed) {
+                    println!(
+                        "idle detector detected that we are no longer idle, so sending IdleEnded message",
+                    );
+                    idle_detector
+                        .restart_wait_time_sender
+                        .send(InnerMsg::IdleEnded(idle_time)).expect("TODO: figure out what to do about channels potentially failing");
+                }
             }
         }
     }
@@ -124,6 +268,30 @@ const fn has_been_idle(
         >= idle_detection_milliseconds
 }
 
+/// Figure out whether the user has just become active again after being idle
+/// for a time that is too short to count as a break.  This returns how long
+/// the user was idle for.
+///
+/// Since the user could have become active at any time since we last checked,
+/// this is only a lower bound.
+fn short_idle_ended(
+    idle_detection_milliseconds: u32,
+    last_milliseconds_since_user_input: u32,
+    milliseconds_since_user_input: u32,
+) -> Option<Duration> {
+    let has_become_active =
+        milliseconds_since_user_input < last_milliseconds_since_user_input;
+    let is_short_idle = (SHORT_IDLE_MILLISECONDS..idle_detection_milliseconds)
+        .contains(&last_milliseconds_since_user_input);
+    if has_become_active && is_short_idle {
+        Some(Duration::from_millis(
+            last_milliseconds_since_user_input.into(),
+        ))
+    } else {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -138,4 +306,23 @@ mod tests {
 
         assert_eq!(res, true);
     }
+
+    #[test]
+    fn test_short_idle_ended() {
+        // The user was idle for 3 minutes, and then became active again.
+        assert_eq!(
+            short_idle_ended(480_000, 180_000, 2_000),
+            Some(Duration::from_secs(180))
+        );
+
+        // The user is still idle.
+        assert_eq!(short_idle_ended(480_000, 180_000, 200_000), None);
+
+        // The user wasn't idle for long enough for it to count.
+        assert_eq!(short_idle_ended(480_000, 20_000, 2_000), None);
+
+        // The user was idle long enough for it to count as a break, so this
+        // has already been reported.
+        assert_eq!(short_idle_ended(480_000, 500_000, 2_000), None);
+    }
 }
@@ -0,0 +1,367 @@
+// This module is an event-driven idle detector that uses the IDLETIME system counter from the
+// X Server's SYNC extension.
+//
+// Two alarms are set on the IDLETIME counter.  The first fires when the counter goes above the
+// idle detection time (the user has become idle), and the second fires when the counter drops back
+// below it (the user has become active again).  The X Server tells us when these alarms trigger,
+// so we never have to wake up and poll.
+//
+// Another pair of alarms does the same for `SHORT_IDLE_MILLISECONDS`, so that idle times that are
+// too short to count as a break can still be reported.
+//
+// The creation of alarms is done through the raw FFI functions, because the safe wrappers in the
+// xcb crate don't provide a way to construct the value list for an alarm.
+
+#![allow(unsafe_code)]
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::super::InnerMsg;
+use super::{IdleDetectionTime, SHORT_IDLE_MILLISECONDS};
+
+/// The name of the system counter that counts the number of milliseconds
+/// since the last user input.
+const IDLETIME_COUNTER_NAME: &str = "IDLETIME";
+
+/// The version of the SYNC extension we ask the X Server for.
+const SYNC_MAJOR_VERSION: u8 = 3;
+const SYNC_MINOR_VERSION: u8 = 1;
+
+/// All the values of an alarm are always given, so that the value list has the
+/// same layout as `xcb_sync_create_alarm_value_list_t`.
+const ALARM_VALUE_MASK: u32 = xcb::sync::CA_COUNTER
+    | xcb::sync::CA_VALUE_TYPE
+    | xcb::sync::CA_VALUE
+    | xcb::sync::CA_TEST_TYPE
+    | xcb::sync::CA_DELTA
+    | xcb::sync::CA_EVENTS;
+
+pub struct XSyncIdleDetector {
+    conn: xcb::Connection,
+    idletime_counter: xcb::sync::Counter,
+    idle_alarm: xcb::sync::Alarm,
+    active_alarm: xcb::sync::Alarm,
+    short_idle_alarm: xcb::sync::Alarm,
+    short_active_alarm: xcb::sync::Alarm,
+    alarm_notify_event: u8,
+}
+
+impl XSyncIdleDetector {
+    /// Connect to the X Server and set up the alarms on the IDLETIME counter.
+    ///
+    /// This returns an error if the X Server doesn't have the SYNC
+    /// extension, or it doesn't provide the IDLETIME counter.
+    pub fn new(idle_detection_milliseconds: u32) -> Result<Self, ()> {
+        let (conn, _) = xcb::Connection::connect(None).map_err(|err| {
+            println!("xsync idle detector: could not connect to X: {}", err);
+        })?;
+
+        let first_event = conn
+            .get_extension_data(xcb::sync::id())
+            .filter(xcb::QueryExtensionData::present)
+            .map(|extension_data| extension_data.first_event())
+            .ok_or_else(|| {
+                println!("xsync idle detector: the X Server does not have the SYNC extension");
+            })?;
+
+        xcb::sync::initialize(&conn, SYNC_MAJOR_VERSION, SYNC_MINOR_VERSION)
+            .get_reply()
+        .map_err(|err| {
+            println!(
+                "xsync idle detector: could not initialize the SYNC extension: {:?}",
+                err
+            );
+        })?;
+
+        let idletime_counter = find_idletime_counter(&conn)?;
+
+        let idle_alarm = conn.generate_id();
+        let active_alarm = conn.generate_id();
+        let short_idle_alarm = conn.generate_id();
+        let short_active_alarm = conn.generate_id();
+
+        let idle_detector = Self {
+            conn,
+            idletime_counter,
+            idle_alarm,
+            active_alarm,
+            short_idle_alarm,
+            short_active_alarm,
+            alarm_notify_event: first_event + xcb::sync::ALARM_NOTIFY,
+        };
+
+        idle_detector.create_alarm(
+            idle_alarm,
+            idle_detection_milliseconds,
+            xcb::sync::TESTTYPE_POSITIVE_TRANSITION,
+        );
+        idle_detector.create_alarm(
+            active_alarm,
+            idle_detection_milliseconds,
+            xcb::sync::TESTTYPE_NEGATIVE_TRANSITION,
+        );
+        idle_detector.create_alarm(
+            short_idle_alarm,
+            SHORT_IDLE_MILLISECONDS,
+            xcb::sync::TESTTYPE_POSITIVE_TRANSITION,
+        );
+        idle_detector.create_alarm(
+            short_active_alarm,
+            SHORT_IDLE_MILLISECONDS,
+            xcb::sync::TESTTYPE_NEGATIVE_TRANSITION,
+        );
+
+        if idle_detector.conn.flush() {
+            Ok(idle_detector)
+        } else {
+            println!("xsync idle detector: could not flush the X connection");
+            Err(())
+        }
+    }
+
+    pub fn run(
+        &self,
+        idle_detection_time: &IdleDetectionTime,
+        idle_detection_enabled: &Arc<AtomicBool>,
+        restart_wait_time_sender: &Sender<InnerMsg>,
+    ) -> Result<(), ()> {
+        // The time when the user became idle, if they are currently idle.
+        let mut opt_idle_start: Option<Instant> = None;
+        // The time when the user became idle for `SHORT_IDLE_MILLISECONDS`,
+        // if they are currently idle.
+        let mut opt_short_idle_start: Option<Instant> = None;
+        // Whether the current idle time has been long enough to count as a
+        // break.
+        let mut has_been_idle = false;
+
+        loop {
+            let event = self.conn.wait_for_event().ok_or_else(|| {
+                println!("xsync idle detector: lost the connection to X");
+            })?;
+
+            if event.response_type() == 0 {
+                let err: &xcb::GenericError =
+                    unsafe { xcb::cast_event(&event) };
+                println!(
+                    "xsync idle detector: got an error from X: {}",
+                    err.error_code()
+                );
+                continue;
+            }
+
+            if event.response_type() & !0x80 != self.alarm_notify_event {
+                continue;
+            }
+
+            let alarm_notify: &xcb::sync::AlarmNotifyEvent =
+                unsafe { xcb::cast_event(&event) };
+
+            let idle_detection_time = Duration::from_millis(
+                idle_detection_time.milliseconds().into(),
+            );
+            let short_idle_time =
+                Duration::from_millis(SHORT_IDLE_MILLISECONDS.into());
+            let opt_msg = if alarm_notify.alarm() == self.idle_alarm {
+                println!("xsync idle detector: the user has become idle");
+                let now = Instant::now();
+                opt_idle_start =
+                    Some(now.checked_sub(idle_detection_time).unwrap_or(now));
+                has_been_idle = true;
+                Some(InnerMsg::HasBeenIdle)
+            } else if alarm_notify.alarm() == self.active_alarm {
+                println!("xsync idle detector: the user has become active");
+                let idle_time = opt_idle_start
+                    .take()
+                    .map_or(idle_detection_time, |idle_start| {
+                        idle_start.elapsed()
+                    });
+                Some(InnerMsg::IdleEnded(idle_time))
+            } else if alarm_notify.alarm() == self.short_idle_alarm {
+                let now = Instant::now();
+                opt_short_idle_start =
+                    Some(now.checked_sub(short_idle_time).unwrap_or(now));
+                None
+            } else if alarm_notify.alarm() == self.short_active_alarm {
+                let idle_time = opt_short_idle_start
+                    .take()
+                    .map_or(short_idle_time, |idle_start| idle_start.elapsed());
+                // Idle times that are long enough to count as a break have
+                // already been reported by the other alarms.
+                if std::mem::take(&mut has_been_idle)
+                    || idle_time >= idle_detection_time
+                {
+                    None
+                } else {
+                    println!("xsync idle detector: the user has become active after a short idle time");
+                    Some(InnerMsg::ShortIdleEnded(idle_time))
+                }
+            } else {
+                continue;
+            };
+
+            // An alarm with a delta of 0 becomes inactive after it triggers,
+            // so it needs to be turned back on.
+            self.reactivate_alarm(alarm_notify.alarm());
+
+            let msg = match opt_msg {
+                None => continue,
+                Some(msg) => msg,
+            };
+
+            if idle_detection_enabled.load(Ordering::Relaxed) {
+                restart_wait_time_sender.send(msg).expect(
+                    "TODO: figure out what to do about channels potentially failing",
+                );
+            } else {
+                println!(
+                    "xsync idle detector: idle_detection is not enabled, so not sending {:?} message",
+                    msg
+                );
+            }
+        }
+    }
+
+    /// Change the idle detection time of both alarms.  This also makes them
+    /// active again.
+    pub fn set_idle_detection_time(&self, idle_detection_milliseconds: u32) {
+        self.change_alarm(
+            self.idle_alarm,
+            idle_detection_milliseconds,
+            xcb::sync::TESTTYPE_POSITIVE_TRANSITION,
+        );
+        self.change_alarm(
+            self.active_alarm,
+            idle_detection_milliseconds,
+            xcb::sync::TESTTYPE_NEGATIVE_TRANSITION,
+        );
+        self.conn.flush();
+    }
+
+    fn alarm_value_list(
+        &self,
+        idle_detection_milliseconds: u32,
+        test_type: xcb::sync::Testtype,
+    ) -> xcb::ffi::sync::xcb_sync_create_alarm_value_list_t {
+        xcb::ffi::sync::xcb_sync_create_alarm_value_list_t {
+            counter: self.idletime_counter,
+            valueType: xcb::sync::VALUETYPE_ABSOLUTE,
+            value: to_int64(idle_detection_milliseconds.into()),
+            testType: test_type,
+            delta: to_int64(0),
+            events: 1,
+        }
+    }
+
+    fn create_alarm(
+        &self,
+        alarm: xcb::sync::Alarm,
+        idle_detection_milliseconds: u32,
+        test_type: xcb::sync::Testtype,
+    ) {
+        let value_list =
+            self.alarm_value_list(idle_detection_milliseconds, test_type);
+        unsafe {
+            xcb::ffi::sync::xcb_sync_create_alarm(
+                self.conn.get_raw_conn(),
+                alarm,
+                ALARM_VALUE_MASK,
+                &value_list,
+            );
+        }
+    }
+
+    fn change_alarm(
+        &self,
+        alarm: xcb::sync::Alarm,
+        idle_detection_milliseconds: u32,
+        test_type: xcb::sync::Testtype,
+    ) {
+        let value_list =
+            self.alarm_value_list(idle_detection_milliseconds, test_type);
+        // The value lists for creating and changing an alarm have the same
+        // layout.
+        unsafe {
+            xcb::ffi::sync::xcb_sync_change_alarm(
+                self.conn.get_raw_conn(),
+                alarm,
+                ALARM_VALUE_MASK,
+                (&value_list as *const xcb::ffi::sync::xcb_sync_create_alarm_value_list_t)
+                    .cast(),
+            );
+        }
+    }
+
+    fn reactivate_alarm(&self, alarm: xcb::sync::Alarm) {
+        // Changing an alarm with no values is enough to make it active again.
+        unsafe {
+            xcb::ffi::sync::xcb_sync_change_alarm(
+                self.conn.get_raw_conn(),
+                alarm,
+                0,
+                std::ptr::null(),
+            );
+        }
+        self.conn.flush();
+    }
+}
+
+impl Drop for XSyncIdleDetector {
+    fn drop(&mut self) {
+        xcb::sync::destroy_alarm(&self.conn, self.idle_alarm);
+        xcb::sync::destroy_alarm(&self.conn, self.active_alarm);
+        xcb::sync::destroy_alarm(&self.conn, self.short_idle_alarm);
+        xcb::sync::destroy_alarm(&self.conn, self.short_active_alarm);
+        self.conn.flush();
+    }
+}
+
+fn find_idletime_counter(
+    conn: &xcb::Connection,
+) -> Result<xcb::sync::Counter, ()> {
+    let system_counters = xcb::sync::list_system_counters(conn)
+        .get_reply()
+        .map_err(|err| {
+            println!(
+                "xsync idle detector: could not list the system counters: {:?}",
+                err
+            );
+        })?;
+
+    let opt_idletime_counter = system_counters
+        .counters()
+        .find(|system_counter| system_counter.name() == IDLETIME_COUNTER_NAME)
+        .map(|system_counter| system_counter.counter());
+
+    opt_idletime_counter.ok_or_else(|| {
+        println!(
+            "xsync idle detector: the X Server does not have an {} counter",
+            IDLETIME_COUNTER_NAME
+        );
+    })
+}
+
+/// Convert a value into the 64-bit integer type used by the SYNC extension.
+const fn to_int64(value: i64) -> xcb::ffi::sync::xcb_sync_int64_t {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    xcb::ffi::sync::xcb_sync_int64_t {
+        hi: (value >> 32) as i32,
+        lo: value as u32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_int64() {
+        let small = to_int64(300_000);
+        assert_eq!((small.hi, small.lo), (0, 300_000));
+
+        let large = to_int64((5 << 32) + 7);
+        assert_eq!((large.hi, large.lo), (5, 7));
+    }
+}
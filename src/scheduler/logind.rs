@@ -0,0 +1,345 @@
+// This module listens to systemd-logind over D-Bus, so that the time the computer is suspended or
+// the screen is locked can be counted as a break.
+//
+// logind sends a `PrepareForSleep` signal with `true` right before the computer is suspended, and
+// with `false` right after it wakes up again.  The session sends `Lock` and `Unlock` signals when
+// the screen gets locked and unlocked.  The screen is often locked right before suspending, so
+// both are combined into a single period of time away from the computer.  Once the user is back,
+// the scheduler is told how long they were away.
+//
+// - https://www.freedesktop.org/software/systemd/man/org.freedesktop.login1.html
+
+use dbus::blocking::Connection;
+use dbus::message::MatchRule;
+use dbus::{Message, Path};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::InnerMsg;
+use crate::clock::BootInstant;
+
+const LOGIND_BUS_NAME: &str = "org.freedesktop.login1";
+const LOGIND_PATH: &str = "/org/freedesktop/login1";
+const MANAGER_INTERFACE: &str = "org.freedesktop.login1.Manager";
+const SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
+
+const METHOD_CALL_TIMEOUT_SECONDS: u64 = 5;
+
+/// How long to wait for a signal before checking the connection again.
+const PROCESS_TIMEOUT_SECONDS: u64 = 60;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Event {
+    SleepStarted,
+    SleepEnded,
+    Locked,
+    Unlocked,
+}
+
+/// Combines suspends and screen locks into periods of time away from the
+/// computer.
+#[derive(Debug, Default)]
+struct AwayTracker {
+    sleeping: bool,
+    locked: bool,
+    /// When the user went away, if they are currently away.
+    away_since: Option<BootInstant>,
+}
+
+impl AwayTracker {
+    /// Keep track of an event from logind.  This returns the amount of time
+    /// the user was away once the computer is neither suspended nor locked.
+    fn handle(&mut self, event: Event, now: BootInstant) -> Option<Duration> {
+        match event {
+            Event::SleepStarted => self.sleeping = true,
+            Event::SleepEnded => self.sleeping = false,
+            Event::Locked => self.locked = true,
+            Event::Unlocked => self.locked = false,
+        }
+
+        if self.sleeping || self.locked {
+            self.away_since.get_or_insert(now);
+            None
+        } else {
+            self.away_since
+                .take()
+                .map(|away_since| now.duration_since(away_since))
+        }
+    }
+}
+
+struct Handler {
+    away_tracker: AwayTracker,
+    restart_wait_time_sender: Sender<InnerMsg>,
+}
+
+impl Handler {
+    fn handle(&mut self, event: Event) {
+        println!("logind: got {:?}", event);
+        if let Some(away_time) =
+            self.away_tracker.handle(event, BootInstant::now())
+        {
+            println!(
+                "logind: the computer was suspended or locked for {:?}, so sending LockedOrSuspended message",
+                away_time
+            );
+            self.restart_wait_time_sender
+                .send(InnerMsg::LockedOrSuspended(away_time))
+                .expect("TODO: figure out what to do about channels potentially failing");
+        }
+    }
+}
+
+/// Find the D-Bus object path of the session break-time is running in.
+fn session_path(conn: &Connection) -> Result<Path<'static>, dbus::Error> {
+    let proxy = conn.with_proxy(
+        LOGIND_BUS_NAME,
+        LOGIND_PATH,
+        Duration::from_secs(METHOD_CALL_TIMEOUT_SECONDS),
+    );
+    // "auto" is the session of the caller, or the graphical session of the
+    // user if the caller isn't in a session.
+    let (session_path,): (Path<'static>,) =
+        proxy.method_call(MANAGER_INTERFACE, "GetSession", ("auto",))?;
+    Ok(session_path)
+}
+
+/// Subscribe to the logind signals.  They are handled when calling
+/// `conn.process()`.
+fn add_matches(
+    conn: &Connection,
+    session_path: &Path<'static>,
+    restart_wait_time_sender: Sender<InnerMsg>,
+) -> Result<(), dbus::Error> {
+    let handler = Arc::new(Mutex::new(Handler {
+        away_tracker: AwayTracker::default(),
+        restart_wait_time_sender,
+    }));
+
+    let sleep_handler = handler.clone();
+    conn.add_match(
+        MatchRule::new_signal(MANAGER_INTERFACE, "PrepareForSleep")
+            .with_path(LOGIND_PATH),
+        move |(start,): (bool,), _: &Connection, _: &Message| {
+            let event = if start {
+                Event::SleepStarted
+            } else {
+                Event::SleepEnded
+            };
+            sleep_handler
+                .lock()
+                .expect("logind handler mutex poisoned")
+                .handle(event);
+            true
+        },
+    )?;
+
+    for (member, event) in
+        &[("Lock", Event::Locked), ("Unlock", Event::Unlocked)]
+    {
+        let event = *event;
+        let lock_handler = handler.clone();
+        conn.add_match(
+            MatchRule::new_signal(SESSION_INTERFACE, *member)
+                .with_path(session_path.clone()),
+            move |(): (), _: &Connection, _: &Message| {
+                lock_handler
+                    .lock()
+                    .expect("logind handler mutex poisoned")
+                    .handle(event);
+                true
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+fn listen(
+    conn: &Connection,
+    restart_wait_time_sender: Sender<InnerMsg>,
+) -> Result<(), dbus::Error> {
+    let session_path = session_path(conn)?;
+    println!("logind: listening for signals for session {}", session_path);
+    add_matches(conn, &session_path, restart_wait_time_sender)?;
+    loop {
+        conn.process(Duration::from_secs(PROCESS_TIMEOUT_SECONDS))?;
+    }
+}
+
+/// Listen for suspends and screen locks, and send a `LockedOrSuspended`
+/// message every time the user comes back.
+///
+/// This only returns if logind is not available, or the connection to the
+/// system bus is lost.
+pub fn run(restart_wait_time_sender: Sender<InnerMsg>) {
+    let res = Connection::new_system()
+        .and_then(|conn| listen(&conn, restart_wait_time_sender));
+    if let Err(err) = res {
+        println!(
+            "logind: not counting suspends and screen locks as breaks: {}",
+            err
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dbus::channel::Channel;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::mpsc::channel;
+
+    const SESSION_PATH: &str = "/org/freedesktop/login1/session/_31";
+
+    const DBUS_DAEMON_CONFIG: &str = r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-BUS Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <type>session</type>
+  <listen>unix:tmpdir=/tmp</listen>
+  <auth>EXTERNAL</auth>
+  <policy context="default">
+    <allow send_destination="*" eavesdrop="true"/>
+    <allow eavesdrop="true"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>
+"#;
+
+    /// A private dbus-daemon that stands in for the system bus.
+    struct DBusDaemon {
+        child: Child,
+        address: String,
+    }
+
+    impl DBusDaemon {
+        /// Start a dbus-daemon.  This returns `None` if dbus-daemon isn't
+        /// installed.
+        fn start() -> Option<Self> {
+            let config_path = std::env::temp_dir().join(format!(
+                "break-time-test-dbus-{}.conf",
+                std::process::id()
+            ));
+            std::fs::write(&config_path, DBUS_DAEMON_CONFIG).unwrap();
+
+            let mut child = Command::new("dbus-daemon")
+                .arg(format!("--config-file={}", config_path.display()))
+                .arg("--print-address")
+                .arg("--nofork")
+                .stdout(Stdio::piped())
+                .spawn()
+                .ok()?;
+
+            let mut address = String::new();
+            BufReader::new(child.stdout.take().unwrap())
+                .read_line(&mut address)
+                .unwrap();
+            std::fs::remove_file(&config_path).unwrap();
+
+            Some(Self {
+                child,
+                address: address.trim().to_string(),
+            })
+        }
+
+        fn connect(&self) -> Connection {
+            let mut channel = Channel::open_private(&self.address).unwrap();
+            channel.register().unwrap();
+            Connection::from(channel)
+        }
+    }
+
+    impl Drop for DBusDaemon {
+        fn drop(&mut self) {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+
+    fn emit(conn: &Connection, path: &str, interface: &str, member: &str) {
+        emit_msg(conn, Message::new_signal(path, interface, member).unwrap());
+    }
+
+    fn emit_msg(conn: &Connection, msg: Message) {
+        conn.channel().send(msg).unwrap();
+        conn.channel().flush();
+    }
+
+    #[test]
+    fn test_away_tracker() {
+        let start = BootInstant::now();
+        let mut away_tracker = AwayTracker::default();
+
+        // The screen gets locked, and then the computer is suspended.
+        assert_eq!(away_tracker.handle(Event::Locked, start), None);
+        let minutes = |mins: u64| start + Duration::from_secs(mins * 60);
+        assert_eq!(away_tracker.handle(Event::SleepStarted, minutes(1)), None);
+        assert_eq!(away_tracker.handle(Event::SleepEnded, minutes(20)), None);
+        assert_eq!(
+            away_tracker.handle(Event::Unlocked, minutes(21)),
+            Some(Duration::from_secs(21 * 60))
+        );
+
+        // Just suspending also counts.
+        assert_eq!(away_tracker.handle(Event::SleepStarted, minutes(30)), None);
+        assert_eq!(
+            away_tracker.handle(Event::SleepEnded, minutes(35)),
+            Some(Duration::from_secs(5 * 60))
+        );
+    }
+
+    #[test]
+    fn test_signals() {
+        let dbus_daemon = match DBusDaemon::start() {
+            None => {
+                println!("dbus-daemon is not installed, so skipping this test");
+                return;
+            }
+            Some(dbus_daemon) => dbus_daemon,
+        };
+
+        let (sender, receiver) = channel();
+        let (ready_sender, ready_receiver) = channel();
+        let listener_conn = dbus_daemon.connect();
+        std::thread::spawn(move || {
+            add_matches(&listener_conn, &Path::from(SESSION_PATH), sender)
+                .unwrap();
+            ready_sender.send(()).unwrap();
+            // This returns an error once the dbus-daemon is killed.
+            while listener_conn.process(Duration::from_secs(1)).is_ok() {}
+        });
+        ready_receiver.recv().unwrap();
+
+        let logind_conn = dbus_daemon.connect();
+        let sleep = |start: bool| {
+            Message::new_signal(
+                LOGIND_PATH,
+                MANAGER_INTERFACE,
+                "PrepareForSleep",
+            )
+            .unwrap()
+            .append1(start)
+        };
+
+        // Locking a different session is ignored.
+        let other_session_path = "/org/freedesktop/login1/session/_42";
+        emit(&logind_conn, other_session_path, SESSION_INTERFACE, "Lock");
+        emit(
+            &logind_conn,
+            other_session_path,
+            SESSION_INTERFACE,
+            "Unlock",
+        );
+
+        emit(&logind_conn, SESSION_PATH, SESSION_INTERFACE, "Lock");
+        emit_msg(&logind_conn, sleep(true));
+        emit_msg(&logind_conn, sleep(false));
+        emit(&logind_conn, SESSION_PATH, SESSION_INTERFACE, "Unlock");
+
+        let msg = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(matches!(msg, InnerMsg::LockedOrSuspended(_)));
+        assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+    }
+}
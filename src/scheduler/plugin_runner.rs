@@ -0,0 +1,461 @@
+// This module runs the checks for the plugins.
+//
+// Each check runs on its own worker thread, so all the plugins are checked at the same time, and a
+// plugin that hangs (for instance, because of a network request that never finishes) can't block
+// the scheduler.  A check that doesn't finish in time is abandoned, and the plugin's `on_timeout`
+// policy decides what happens.
+//
+// A plugin that keeps failing is disabled, so that a plugin that is permanently broken (for
+// instance, because of an expired OAuth token) can't stop breaks from ever happening.  A plugin
+// also can't delay a break for longer than its `max_deferral_seconds`.
+
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::plugins::{CanBreak, OnError, OnTimeout, Plugin, Policy};
+use crate::prelude::*;
+
+/// If this many checks of a plugin have been abandoned and are still running,
+/// then the plugin isn't checked again until one of them finishes.  This
+/// stops a plugin that always hangs from piling up threads.
+const MAX_ABANDONED_CHECKS: usize = 2;
+
+/// What a plugin answered when asked whether a break can happen.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Answer {
+    Yes,
+    No,
+    Error(String),
+    TimedOut,
+    /// The plugin has been disabled because it failed too many times, so it
+    /// wasn't asked.
+    Disabled,
+}
+
+impl fmt::Display for Answer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Yes => write!(f, "yes"),
+            Self::No => write!(f, "no"),
+            Self::Error(err) => write!(f, "error: {}", err),
+            Self::TimedOut => write!(f, "did not answer in time"),
+            Self::Disabled => write!(f, "disabled"),
+        }
+    }
+}
+
+/// The result of checking a single plugin.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct PluginResult {
+    pub name: String,
+    pub answer: Answer,
+    /// How long the plugin took to answer.
+    #[serde(rename = "milliseconds", serialize_with = "serialize_millis")]
+    pub duration: Duration,
+    /// Whether this result stops a break from happening.
+    pub blocks_break: bool,
+    /// Whether the plugin was disabled because of this result.
+    pub disabled: bool,
+    /// Whether the plugin would have blocked the break, but was ignored
+    /// because it has already delayed the break for too long.
+    pub max_deferral_exceeded: bool,
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn serialize_millis<S: Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer
+        .serialize_u64(u64::try_from(duration.as_millis()).unwrap_or(u64::MAX))
+}
+
+/// A plugin along with the policy for checking it.
+pub struct PluginRunner {
+    plugin: Arc<dyn Plugin>,
+    policy: Policy,
+    /// The number of checks that have been abandoned, but haven't finished.
+    abandoned_checks: Arc<AtomicUsize>,
+    consecutive_failures: u32,
+    disabled: bool,
+}
+
+/// A check that has been started on a worker thread.
+struct PendingCheck {
+    start: Instant,
+    receiver: Receiver<Result<CanBreak, String>>,
+}
+
+impl PluginRunner {
+    pub fn new(plugin: Arc<dyn Plugin>, policy: Policy) -> Self {
+        Self {
+            plugin,
+            policy,
+            abandoned_checks: Arc::new(AtomicUsize::new(0)),
+            consecutive_failures: 0,
+            disabled: false,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.plugin.name()
+    }
+
+    /// Start checking the plugin on a worker thread.  This returns `None` if
+    /// the plugin still hasn't finished too many earlier checks.
+    fn start(&self) -> Option<PendingCheck> {
+        if self.disabled {
+            return None;
+        }
+        if self.abandoned_checks.load(Ordering::SeqCst) >= MAX_ABANDONED_CHECKS
+        {
+            println!(
+                "plugin {} still hasn't finished earlier checks, so not checking it again",
+                self.name()
+            );
+            return None;
+        }
+
+        let (sender, receiver) = channel();
+        let plugin = self.plugin.clone();
+        std::thread::spawn(move || {
+            let res = plugin.can_break_now().map_err(|err| err.to_string());
+            // The scheduler may have stopped waiting for this check.
+            let _ = sender.send(res);
+        });
+
+        Some(PendingCheck {
+            start: Instant::now(),
+            receiver,
+        })
+    }
+
+    /// Wait for a check to finish, up to the timeout for this plugin.
+    fn wait(&self, opt_pending_check: Option<PendingCheck>) -> PluginResult {
+        let (answer, duration) = match opt_pending_check {
+            None if self.disabled => (Answer::Disabled, Duration::from_secs(0)),
+            None => (Answer::TimedOut, Duration::from_secs(0)),
+            Some(pending_check) => {
+                let deadline = pending_check.start + self.policy.timeout;
+                let timeout =
+                    deadline.saturating_duration_since(Instant::now());
+                let answer = match pending_check.receiver.recv_timeout(timeout)
+                {
+                    Ok(Ok(CanBreak::Yes)) => Answer::Yes,
+                    Ok(Ok(CanBreak::No)) => Answer::No,
+                    Ok(Err(err)) => Answer::Error(err),
+                    Err(RecvTimeoutError::Disconnected) => Answer::Error(
+                        String::from("plugin panicked while being checked"),
+                    ),
+                    Err(RecvTimeoutError::Timeout) => {
+                        self.abandon(pending_check.receiver);
+                        Answer::TimedOut
+                    }
+                };
+                (answer, pending_check.start.elapsed())
+            }
+        };
+
+        let blocks_break = match answer {
+            Answer::Yes | Answer::Disabled => false,
+            Answer::No => true,
+            Answer::Error(_) => self.policy.on_error == OnError::BlockBreak,
+            Answer::TimedOut => self.policy.on_timeout != OnTimeout::AllowBreak,
+        };
+
+        PluginResult {
+            name: self.name(),
+            answer,
+            duration,
+            blocks_break,
+            disabled: false,
+            max_deferral_exceeded: false,
+        }
+    }
+
+    /// Keep track of how many times in a row the plugin has failed, and
+    /// disable it if it has failed too many times.
+    fn record(&mut self, result: &mut PluginResult) {
+        match result.answer {
+            Answer::Yes | Answer::No => self.consecutive_failures = 0,
+            Answer::Disabled => {}
+            Answer::Error(_) | Answer::TimedOut => {
+                self.consecutive_failures += 1;
+                let ignore_plugin = matches!(result.answer, Answer::Error(_))
+                    && self.policy.on_error == OnError::IgnorePlugin;
+                let max = self.policy.max_consecutive_failures;
+                if ignore_plugin
+                    || (max != 0 && self.consecutive_failures >= max)
+                {
+                    println!(
+                        "plugin {} failed {} times in a row, so disabling it",
+                        self.name(),
+                        self.consecutive_failures
+                    );
+                    self.disabled = true;
+                    result.disabled = true;
+                    result.blocks_break = false;
+                }
+            }
+        }
+    }
+
+    /// Keep track of a check that is still running, until it finishes.
+    fn abandon(&self, receiver: Receiver<Result<CanBreak, String>>) {
+        let abandoned_checks = self.abandoned_checks.clone();
+        abandoned_checks.fetch_add(1, Ordering::SeqCst);
+        std::thread::spawn(move || {
+            // This returns once the check finishes, whether it succeeded or
+            // not.
+            let _ = receiver.recv();
+            abandoned_checks.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+
+    /// Stop the plugin from blocking the break if it has already delayed the
+    /// break for longer than it is allowed to.
+    fn limit_deferral(&self, result: &mut PluginResult, deferral: Duration) {
+        if let Some(max_deferral) = self.policy.max_deferral {
+            if result.blocks_break && deferral >= max_deferral {
+                println!(
+                    "plugin {} has delayed the break for {:?}, which is longer than {:?}, so not letting it block the break",
+                    self.name(),
+                    deferral,
+                    max_deferral
+                );
+                result.blocks_break = false;
+                result.max_deferral_exceeded = true;
+            }
+        }
+    }
+}
+
+/// Check all the plugins at the same time, and return the result for each
+/// plugin.  Plugins that have been disabled aren't checked.
+///
+/// `deferral` is how long the plugins have already been delaying the break.
+pub fn check_all(
+    runners: &mut [PluginRunner],
+    deferral: Duration,
+) -> Vec<PluginResult> {
+    let pending_checks: Vec<Option<PendingCheck>> =
+        runners.iter().map(PluginRunner::start).collect();
+
+    let mut results: Vec<PluginResult> = runners
+        .iter()
+        .zip(pending_checks)
+        .map(|(runner, opt_pending_check)| runner.wait(opt_pending_check))
+        .collect();
+
+    // Plugins that timed out and should be retried are all asked again at
+    // the same time as well.
+    let retries: Vec<(usize, Option<PendingCheck>)> = runners
+        .iter()
+        .zip(&results)
+        .enumerate()
+        .filter(|(_, (runner, result))| {
+            result.answer == Answer::TimedOut
+                && runner.policy.on_timeout == OnTimeout::Retry
+        })
+        .map(|(i, (runner, _))| {
+            println!("plugin {} timed out, so asking it again", runner.name());
+            (i, runner.start())
+        })
+        .collect();
+    for (i, opt_pending_check) in retries {
+        let first_duration = results[i].duration;
+        results[i] = runners[i].wait(opt_pending_check);
+        results[i].duration += first_duration;
+    }
+
+    for (runner, result) in runners.iter_mut().zip(&mut results) {
+        runner.record(result);
+        runner.limit_deferral(result, deferral);
+    }
+
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    struct TestPlugin {
+        /// How long each call to `can_break_now` takes.  The last delay is
+        /// used for all calls after the others have been used up.
+        delays: Mutex<Vec<Duration>>,
+        /// `None` means the plugin returns an error.
+        can_break: Option<CanBreak>,
+    }
+
+    impl Plugin for TestPlugin {
+        fn can_break_now(
+            &self,
+        ) -> Result<CanBreak, Box<dyn std::error::Error>> {
+            let delay = {
+                let mut delays = self.delays.lock().unwrap();
+                if delays.len() > 1 {
+                    delays.remove(0)
+                } else {
+                    delays[0]
+                }
+            };
+            std::thread::sleep(delay);
+            self.can_break.ok_or_else(|| "test error".into())
+        }
+
+        fn name(&self) -> String {
+            format!("{:?}", self.can_break)
+        }
+    }
+
+    fn runner(
+        delay_millis: &[u64],
+        can_break: Option<CanBreak>,
+        on_timeout: OnTimeout,
+    ) -> PluginRunner {
+        let delays = delay_millis
+            .iter()
+            .map(|millis| Duration::from_millis(*millis))
+            .collect();
+        PluginRunner::new(
+            Arc::new(TestPlugin {
+                delays: Mutex::new(delays),
+                can_break,
+            }),
+            Policy {
+                timeout: Duration::from_millis(200),
+                on_timeout,
+                on_error: OnError::BlockBreak,
+                max_consecutive_failures: 0,
+                max_deferral: None,
+            },
+        )
+    }
+
+    #[test]
+    fn test_check_all() {
+        let mut runners = vec![
+            runner(&[100], Some(CanBreak::Yes), OnTimeout::BlockBreak),
+            runner(&[100], Some(CanBreak::No), OnTimeout::BlockBreak),
+            runner(&[2000], Some(CanBreak::Yes), OnTimeout::AllowBreak),
+            runner(&[2000], Some(CanBreak::Yes), OnTimeout::BlockBreak),
+        ];
+
+        let start = Instant::now();
+        let results = check_all(&mut runners, Duration::from_secs(0));
+
+        // The plugins are checked at the same time, so this only takes about
+        // as long as the timeout.
+        assert!(start.elapsed() < Duration::from_millis(1000));
+
+        let answers: Vec<(&Answer, bool)> = results
+            .iter()
+            .map(|result| (&result.answer, result.blocks_break))
+            .collect();
+        assert_eq!(
+            answers,
+            vec![
+                (&Answer::Yes, false),
+                (&Answer::No, true),
+                (&Answer::TimedOut, false),
+                (&Answer::TimedOut, true),
+            ]
+        );
+        assert!(results[0].duration >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_retry() {
+        // The first check times out, but the second one answers in time.
+        let mut runners =
+            vec![runner(&[1000, 50], Some(CanBreak::No), OnTimeout::Retry)];
+        let results = check_all(&mut runners, Duration::from_secs(0));
+        assert_eq!(results[0].answer, Answer::No);
+        assert!(results[0].duration >= Duration::from_millis(250));
+
+        // Neither check answers in time.
+        let mut runners =
+            vec![runner(&[1000], Some(CanBreak::Yes), OnTimeout::Retry)];
+        let results = check_all(&mut runners, Duration::from_secs(0));
+        assert_eq!(results[0].answer, Answer::TimedOut);
+        assert!(results[0].blocks_break);
+    }
+
+    #[test]
+    fn test_on_error() {
+        let mut runners = vec![
+            runner(&[0], None, OnTimeout::BlockBreak),
+            runner(&[0], None, OnTimeout::BlockBreak),
+            runner(&[0], None, OnTimeout::BlockBreak),
+        ];
+        runners[0].policy.max_consecutive_failures = 2;
+        runners[1].policy.on_error = OnError::AllowBreak;
+        runners[2].policy.on_error = OnError::IgnorePlugin;
+
+        let blocks_and_disabled = |results: Vec<PluginResult>| {
+            results
+                .iter()
+                .map(|result| (result.blocks_break, result.disabled))
+                .collect::<Vec<_>>()
+        };
+
+        let results = check_all(&mut runners, Duration::from_secs(0));
+        assert_eq!(
+            results[0].answer,
+            Answer::Error(String::from("test error"))
+        );
+        assert_eq!(
+            blocks_and_disabled(results),
+            vec![(true, false), (false, false), (false, true)]
+        );
+
+        // The first plugin has now failed twice in a row, so it gets
+        // disabled.
+        let results = check_all(&mut runners, Duration::from_secs(0));
+        assert_eq!(
+            blocks_and_disabled(results),
+            vec![(false, true), (false, false), (false, false)]
+        );
+
+        let results = check_all(&mut runners, Duration::from_secs(0));
+        assert_eq!(results[0].answer, Answer::Disabled);
+        assert_eq!(results[2].answer, Answer::Disabled);
+        assert_eq!(
+            blocks_and_disabled(results),
+            vec![(false, false), (false, false), (false, false)]
+        );
+    }
+
+    #[test]
+    fn test_max_deferral() {
+        let mut runners = vec![
+            runner(&[0], Some(CanBreak::No), OnTimeout::BlockBreak),
+            runner(&[0], Some(CanBreak::No), OnTimeout::BlockBreak),
+        ];
+        runners[0].policy.max_deferral = Some(Duration::from_secs(60 * 60));
+
+        let blocks_break = |results: Vec<PluginResult>| {
+            results
+                .iter()
+                .map(|result| result.blocks_break)
+                .collect::<Vec<_>>()
+        };
+
+        let results = check_all(&mut runners, Duration::from_secs(30 * 60));
+        assert_eq!(blocks_break(results), vec![true, true]);
+
+        // The first plugin has delayed the break for too long, but the second
+        // plugin doesn't have a limit.
+        let results = check_all(&mut runners, Duration::from_secs(60 * 60));
+        assert!(results[0].max_deferral_exceeded);
+        assert!(!results[1].max_deferral_exceeded);
+        assert_eq!(blocks_break(results), vec![false, true]);
+    }
+}
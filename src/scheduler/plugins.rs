@@ -1,9 +1,25 @@
+pub mod capture_devices;
+pub mod command;
+#[cfg(test)]
+mod fake_proc;
+pub mod fullscreen;
 pub mod google_calendar;
-mod window_titles;
+pub mod ics;
+pub mod matcher;
+pub mod processes;
+pub mod window_titles;
 
+pub use capture_devices::CaptureDevices;
+pub use command::Command;
+pub use fullscreen::Fullscreen;
 pub use google_calendar::GoogleCalendar;
+pub use ics::Ics;
+pub use processes::Processes;
 pub use window_titles::WindowTitles;
 
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
 #[derive(Copy, Clone, Debug)]
 pub enum CanBreak {
     Yes,
@@ -35,7 +51,102 @@ impl CanBreak {
     }
 }
 
-pub trait Plugin {
+/// What to do when a plugin doesn't answer in time.
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnTimeout {
+    /// Act as if the plugin said a break can happen.
+    AllowBreak,
+    /// Act as if the plugin said a break can't happen.
+    BlockBreak,
+    /// Ask the plugin again.  If it doesn't answer in time the second time
+    /// either, the break is blocked.
+    Retry,
+}
+
+/// What to do when a plugin returns an error.
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnError {
+    /// Act as if the plugin said a break can happen.
+    AllowBreak,
+    /// Act as if the plugin said a break can't happen.
+    BlockBreak,
+    /// Disable the plugin, so that it isn't asked again.
+    IgnorePlugin,
+}
+
+/// Settings for how the scheduler checks a plugin.
+///
+/// Each plugin section in the config file can have a `policy`, and
+/// `[plugin.default_policy]` applies to all plugins that don't set a value
+/// themselves.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicySettings {
+    /// How long to wait for the plugin to answer.
+    pub timeout_seconds: Option<u32>,
+    pub on_timeout: Option<OnTimeout>,
+    pub on_error: Option<OnError>,
+    /// The plugin is disabled after it fails (returns an error or doesn't
+    /// answer in time) this many times in a row.  0 means the plugin is
+    /// never disabled.
+    pub max_consecutive_failures: Option<u32>,
+    /// Once the plugin has delayed a break for this long, it can't delay the
+    /// break any more, and the break starts anyway.  0 means there is no
+    /// limit.
+    pub max_deferral_seconds: Option<u32>,
+}
+
+const DEFAULT_TIMEOUT_SECONDS: u32 = 30;
+
+const DEFAULT_MAX_CONSECUTIVE_FAILURES: u32 = 5;
+
+impl PolicySettings {
+    /// Figure out the policy for a plugin, using `default_policy` for
+    /// anything not set for the plugin itself.
+    pub fn resolve(&self, default_policy: &Self) -> Policy {
+        let timeout_seconds = self
+            .timeout_seconds
+            .or(default_policy.timeout_seconds)
+            .unwrap_or(DEFAULT_TIMEOUT_SECONDS);
+        Policy {
+            timeout: Duration::from_secs(timeout_seconds.into()),
+            on_timeout: self
+                .on_timeout
+                .or(default_policy.on_timeout)
+                .unwrap_or(OnTimeout::BlockBreak),
+            on_error: self
+                .on_error
+                .or(default_policy.on_error)
+                .unwrap_or(OnError::BlockBreak),
+            max_consecutive_failures: self
+                .max_consecutive_failures
+                .or(default_policy.max_consecutive_failures)
+                .unwrap_or(DEFAULT_MAX_CONSECUTIVE_FAILURES),
+            max_deferral: self
+                .max_deferral_seconds
+                .or(default_policy.max_deferral_seconds)
+                .filter(|max_deferral_seconds| *max_deferral_seconds != 0)
+                .map(|max_deferral_seconds| {
+                    Duration::from_secs(max_deferral_seconds.into())
+                }),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Policy {
+    pub timeout: Duration,
+    pub on_timeout: OnTimeout,
+    pub on_error: OnError,
+    pub max_consecutive_failures: u32,
+    pub max_deferral: Option<Duration>,
+}
+
+/// Plugins are checked on worker threads, so they have to be `Send` and
+/// `Sync`.
+pub trait Plugin: Send + Sync {
     fn can_break_now(&self) -> Result<CanBreak, Box<dyn std::error::Error>>;
 
     fn name(&self) -> String;
@@ -0,0 +1,350 @@
+// This module defines a plugin that stops breaks from happening while a camera or microphone is
+// being used, which almost always means the user is on a call.
+//
+// Cameras are found by looking through the open file descriptors of every process in
+// /proc/PID/fd for a /dev/video* device.  Microphones are found through ALSA, which has a status
+// file for every capture substream at /proc/asound/cardN/pcmMc/subK/status.  The status file just
+// says "closed" when nothing is recording, and otherwise has the PID of the process that opened
+// it.  Sound servers like PulseAudio and PipeWire hold the ALSA device open while any of their
+// clients are recording, so the process reported for a microphone is often the sound server.
+//
+// - https://www.kernel.org/doc/html/latest/sound/designs/procfile.html
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::{CanBreak, Plugin, PolicySettings};
+use crate::config::Config;
+
+const PROC_ROOT: &str = "/proc";
+const VIDEO_DEVICE_PREFIX: &str = "/dev/video";
+
+/// Settings for the `capture_devices` plugin.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Stop breaks while a camera (`/dev/video*`) is open.
+    #[serde(default = "default_true")]
+    pub camera: bool,
+    /// Stop breaks while an ALSA capture device is open.
+    #[serde(default = "default_true")]
+    pub microphone: bool,
+    #[serde(default)]
+    pub policy: PolicySettings,
+}
+
+const fn default_true() -> bool {
+    true
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            camera: default_true(),
+            microphone: default_true(),
+            policy: PolicySettings::default(),
+        }
+    }
+}
+
+/// A capture device that is in use.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct OpenDevice {
+    /// Something like `/dev/video0` or `card0/pcm0c/sub0`.
+    device: String,
+    pid: Option<u32>,
+    /// The name of the process from /proc/PID/comm.
+    process: Option<String>,
+}
+
+fn read_comm(proc_root: &Path, pid: u32) -> Option<String> {
+    std::fs::read_to_string(proc_root.join(pid.to_string()).join("comm"))
+        .ok()
+        .map(|comm| comm.trim_end().to_string())
+}
+
+/// The entries of `dir` with a name starting with `prefix`, sorted by name.
+/// This is empty if `dir` can't be read.
+fn entries_with_prefix(dir: &Path, prefix: &str) -> Vec<(String, PathBuf)> {
+    let mut entries: Vec<(String, PathBuf)> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if name.starts_with(prefix) {
+                Some((name, entry.path()))
+            } else {
+                None
+            }
+        })
+        .collect();
+    entries.sort();
+    entries
+}
+
+/// Find all the processes with a camera open.
+fn open_cameras(proc_root: &Path) -> std::io::Result<Vec<OpenDevice>> {
+    let mut open_devices = vec![];
+    for entry in std::fs::read_dir(proc_root)? {
+        let entry = entry?;
+        let pid: u32 =
+            match entry.file_name().to_str().and_then(|s| s.parse().ok()) {
+                None => continue,
+                Some(pid) => pid,
+            };
+        // Only the file descriptors of our own processes can be read, so
+        // errors for other processes are expected.
+        let fds = match std::fs::read_dir(entry.path().join("fd")) {
+            Err(_) => continue,
+            Ok(fds) => fds,
+        };
+        for fd in fds.filter_map(Result::ok) {
+            if let Ok(target) = std::fs::read_link(fd.path()) {
+                let device = target.to_string_lossy();
+                if device.starts_with(VIDEO_DEVICE_PREFIX) {
+                    open_devices.push(OpenDevice {
+                        device: device.into_owned(),
+                        pid: Some(pid),
+                        process: read_comm(proc_root, pid),
+                    });
+                }
+            }
+        }
+    }
+    Ok(open_devices)
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum SubstreamStatus {
+    Closed,
+    /// `owner_pid` is the process that opened the substream, if it is known.
+    Open {
+        owner_pid: Option<u32>,
+    },
+}
+
+/// Parse the status file of an ALSA substream.
+fn parse_substream_status(status: &str) -> SubstreamStatus {
+    if status.trim() == "closed" {
+        return SubstreamStatus::Closed;
+    }
+    let owner_pid = status.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        if key.trim() == "owner_pid" {
+            value.trim().parse().ok()
+        } else {
+            None
+        }
+    });
+    SubstreamStatus::Open { owner_pid }
+}
+
+/// Find all the ALSA capture substreams that are open.
+fn open_microphones(proc_root: &Path) -> Vec<OpenDevice> {
+    let mut open_devices = vec![];
+    // /proc/asound also has symlinks to the cards with their names, so only
+    // the `cardN` directories are used to avoid finding every device twice.
+    for (card, card_dir) in
+        entries_with_prefix(&proc_root.join("asound"), "card")
+    {
+        for (pcm, pcm_dir) in entries_with_prefix(&card_dir, "pcm") {
+            // Playback devices end in "p" and capture devices end in "c".
+            if !pcm.ends_with('c') {
+                continue;
+            }
+            for (sub, sub_dir) in entries_with_prefix(&pcm_dir, "sub") {
+                let status = std::fs::read_to_string(sub_dir.join("status"))
+                    .map_or(SubstreamStatus::Closed, |status| {
+                        parse_substream_status(&status)
+                    });
+                if let SubstreamStatus::Open { owner_pid } = status {
+                    open_devices.push(OpenDevice {
+                        device: format!("{}/{}/{}", card, pcm, sub),
+                        pid: owner_pid,
+                        process: owner_pid
+                            .and_then(|pid| read_comm(proc_root, pid)),
+                    });
+                }
+            }
+        }
+    }
+    open_devices
+}
+
+pub struct CaptureDevices {
+    proc_root: PathBuf,
+    settings: Settings,
+}
+
+impl CaptureDevices {
+    pub fn new(config: &Config) -> Self {
+        Self::with_proc_root(
+            &config.settings.all_plugin_settings.capture_devices,
+            PathBuf::from(PROC_ROOT),
+        )
+    }
+
+    fn with_proc_root(settings: &Settings, proc_root: PathBuf) -> Self {
+        Self {
+            proc_root,
+            settings: settings.clone(),
+        }
+    }
+
+    fn open_devices(&self) -> std::io::Result<Vec<OpenDevice>> {
+        let mut open_devices = vec![];
+        if self.settings.camera {
+            open_devices.extend(open_cameras(&self.proc_root)?);
+        }
+        if self.settings.microphone {
+            open_devices.extend(open_microphones(&self.proc_root));
+        }
+        Ok(open_devices)
+    }
+
+    fn can_break(&self) -> std::io::Result<CanBreak> {
+        let open_devices = self.open_devices()?;
+        for open_device in &open_devices {
+            println!(
+                "capture_devices: {} is being used by {} (pid {})",
+                open_device.device,
+                open_device.process.as_deref().unwrap_or("unknown process"),
+                open_device.pid.map_or_else(
+                    || String::from("unknown"),
+                    |pid| pid.to_string()
+                )
+            );
+        }
+        Ok(CanBreak::from_bool(open_devices.is_empty()))
+    }
+}
+
+impl Plugin for CaptureDevices {
+    fn can_break_now(&self) -> Result<CanBreak, Box<dyn std::error::Error>> {
+        self.can_break()
+            .map_err(|err| Box::new(err) as Box<dyn std::error::Error>)
+    }
+
+    fn name(&self) -> String {
+        String::from("capture_devices")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::scheduler::plugins::fake_proc::FakeProc;
+
+    /// A directory that looks like /proc, with a sound card that has a
+    /// single capture device.
+    fn fake_proc(test_name: &str) -> FakeProc {
+        let fake_proc = FakeProc::new(&format!("capture-{}", test_name));
+        let card = fake_proc.root.join("asound/card0");
+        for sub in &["pcm0p/sub0", "pcm0c/sub0"] {
+            std::fs::create_dir_all(card.join(sub)).unwrap();
+            std::fs::write(card.join(sub).join("status"), "closed\n").unwrap();
+        }
+        std::os::unix::fs::symlink("card0", fake_proc.root.join("asound/PCH"))
+            .unwrap();
+        fake_proc
+    }
+
+    fn add_process(fake_proc: &FakeProc, pid: u32, comm: &str, fds: &[&str]) {
+        let dir = fake_proc.add_process(pid, comm);
+        std::fs::create_dir_all(dir.join("fd")).unwrap();
+        for (i, target) in fds.iter().enumerate() {
+            std::os::unix::fs::symlink(
+                target,
+                dir.join("fd").join(i.to_string()),
+            )
+            .unwrap();
+        }
+    }
+
+    fn set_capture_status(fake_proc: &FakeProc, status: &str) {
+        std::fs::write(
+            fake_proc.root.join("asound/card0/pcm0c/sub0/status"),
+            status,
+        )
+        .unwrap();
+    }
+
+    fn new_plugin(fake_proc: &FakeProc, settings: &Settings) -> CaptureDevices {
+        CaptureDevices::with_proc_root(settings, fake_proc.root.clone())
+    }
+
+    const CAPTURE_RUNNING: &str = "state: RUNNING
+owner_pid   : 200
+trigger_time: 1234.567890
+tstamp      : 1234.600000
+delay       : 256
+avail       : 0
+avail_max   : 512
+-----
+hw_ptr      : 1024
+appl_ptr    : 1280
+";
+
+    #[test]
+    fn test_parse_substream_status() {
+        assert_eq!(parse_substream_status("closed\n"), SubstreamStatus::Closed);
+        assert_eq!(
+            parse_substream_status(CAPTURE_RUNNING),
+            SubstreamStatus::Open {
+                owner_pid: Some(200)
+            }
+        );
+        assert_eq!(
+            parse_substream_status("state: PREPARED\n"),
+            SubstreamStatus::Open { owner_pid: None }
+        );
+    }
+
+    #[test]
+    fn test_open_devices() {
+        let fake_proc = fake_proc("open");
+        add_process(&fake_proc, 100, "bash", &["/dev/pts/0", "/dev/null"]);
+        add_process(&fake_proc, 200, "pipewire", &["/dev/snd/pcmC0D0c"]);
+
+        let plugin = new_plugin(&fake_proc, &Settings::default());
+        assert!(plugin.can_break().unwrap().into_bool());
+
+        add_process(&fake_proc, 300, "zoom", &["/dev/null", "/dev/video0"]);
+        assert_eq!(
+            plugin.open_devices().unwrap(),
+            vec![OpenDevice {
+                device: String::from("/dev/video0"),
+                pid: Some(300),
+                process: Some(String::from("zoom")),
+            }]
+        );
+        assert!(!plugin.can_break().unwrap().into_bool());
+
+        let no_camera = Settings {
+            camera: false,
+            ..Settings::default()
+        };
+        assert!(new_plugin(&fake_proc, &no_camera)
+            .can_break()
+            .unwrap()
+            .into_bool());
+
+        // The microphone is only found once, even though the card is also
+        // linked as PCH.
+        set_capture_status(&fake_proc, CAPTURE_RUNNING);
+        assert_eq!(
+            new_plugin(&fake_proc, &no_camera).open_devices().unwrap(),
+            vec![OpenDevice {
+                device: String::from("card0/pcm0c/sub0"),
+                pid: Some(200),
+                process: Some(String::from("pipewire")),
+            }]
+        );
+    }
+}
@@ -0,0 +1,256 @@
+// This module defines a plugin that runs a command from the user to decide whether or not a break
+// can happen.
+//
+// The command is run with `sh -c` every time the scheduler wants to know whether it can break.
+// If the command exits with 0, then a break can happen.  If it exits with 1, then a break can't
+// happen.  Any other exit status (or the command taking too long) is an error.
+//
+// The command is started in its own process group, so that when it takes too long, everything it
+// started is killed too, and not just the `sh` running it.
+
+#![allow(unsafe_code)]
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Child, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::{CanBreak, Plugin, PolicySettings};
+use crate::prelude::*;
+
+/// How often to check whether the command has finished.
+const POLL_MILLISECONDS: u64 = 50;
+
+/// Settings for a single `[[plugin.command]]` section.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    /// The name of this plugin, used for logging and for telling the user
+    /// what is blocking a break.  Defaults to the command itself.
+    pub name: Option<String>,
+    /// The command to run.  This is passed to `sh -c`.
+    pub cmd: String,
+    /// The command is killed if it takes longer than this.
+    #[serde(default = "default_timeout_seconds")]
+    pub timeout_seconds: u32,
+    /// The directory to run the command in.  A leading `~` is replaced with
+    /// the home directory.  Defaults to the directory break-time was
+    /// started in.
+    pub working_dir: Option<String>,
+    /// Extra environment variables to set for the command.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub policy: PolicySettings,
+}
+
+const fn default_timeout_seconds() -> u32 {
+    10
+}
+
+impl Settings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.cmd.trim().is_empty() {
+            Err(String::from("cmd must not be empty"))
+        } else if self.timeout_seconds == 0 {
+            Err(String::from("timeout_seconds must be more than 0"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Spawn(std::io::Error),
+    Wait(std::io::Error),
+    Timeout(Duration),
+    /// The command exited with a status other than 0 or 1, or was killed by
+    /// a signal.
+    ExitStatus(ExitStatus),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Spawn(err) => write!(f, "could not run command: {}", err),
+            Self::Wait(err) => {
+                write!(f, "could not wait for command to finish: {}", err)
+            }
+            Self::Timeout(timeout) => {
+                write!(f, "command did not finish within {:?}", timeout)
+            }
+            Self::ExitStatus(exit_status) => {
+                write!(f, "command failed with {}", exit_status)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub struct Command {
+    name: String,
+    settings: Settings,
+}
+
+impl Command {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            name: settings
+                .name
+                .clone()
+                .unwrap_or_else(|| settings.cmd.clone()),
+            settings: settings.clone(),
+        }
+    }
+
+    fn spawn(&self) -> Result<Child, Error> {
+        let mut command = std::process::Command::new("sh");
+        command
+            .arg("-c")
+            .arg(&self.settings.cmd)
+            .envs(&self.settings.env)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null());
+        if let Some(working_dir) = &self.settings.working_dir {
+            command.current_dir(expand_tilde(working_dir));
+        }
+        // setpgid() is async-signal-safe, so it can be called between fork()
+        // and exec().
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setpgid(0, 0) == 0 {
+                    Ok(())
+                } else {
+                    Err(std::io::Error::last_os_error())
+                }
+            });
+        }
+        command.spawn().map_err(Error::Spawn)
+    }
+
+    fn run(&self) -> Result<CanBreak, Error> {
+        let timeout = Duration::from_secs(self.settings.timeout_seconds.into());
+        let start = Instant::now();
+        let mut child = self.spawn()?;
+
+        let exit_status = loop {
+            if let Some(exit_status) = child.try_wait().map_err(Error::Wait)? {
+                break exit_status;
+            }
+            if start.elapsed() >= timeout {
+                // The command may have already exited on its own, so errors
+                // here can be ignored.
+                kill_process_group(&child);
+                let _ = child.wait();
+                return Err(Error::Timeout(timeout));
+            }
+            std::thread::sleep(Duration::from_millis(POLL_MILLISECONDS));
+        };
+
+        match exit_status.code() {
+            Some(0) => Ok(CanBreak::Yes),
+            Some(1) => Ok(CanBreak::No),
+            _ => Err(Error::ExitStatus(exit_status)),
+        }
+    }
+}
+
+/// Kill `child` and everything else in its process group.
+fn kill_process_group(child: &Child) {
+    if let Ok(pid) = libc::pid_t::try_from(child.id()) {
+        // The child put itself in a new process group before running the
+        // command, so its process group ID is the same as its process ID.
+        unsafe {
+            libc::kill(-pid, libc::SIGKILL);
+        }
+    }
+}
+
+pub fn expand_tilde(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), std::env::var_os("HOME")) {
+        (Some(rest), Some(home))
+            if rest.is_empty() || rest.starts_with('/') =>
+        {
+            PathBuf::from(home).join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+impl Plugin for Command {
+    fn can_break_now(&self) -> Result<CanBreak, Box<dyn std::error::Error>> {
+        self.run()
+            .map_err(|err| Box::new(err) as Box<dyn std::error::Error>)
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(cmd: &str) -> Command {
+        let settings: Settings =
+            toml::from_str(&format!("cmd = {:?}\ntimeout_seconds = 1", cmd))
+                .unwrap();
+        Command::new(&settings)
+    }
+
+    #[test]
+    fn test_exit_status() {
+        assert!(command("true").run().unwrap().into_bool());
+        assert!(!command("exit 1").run().unwrap().into_bool());
+        assert!(matches!(command("exit 2").run(), Err(Error::ExitStatus(_))));
+        assert!(matches!(command("sleep 5").run(), Err(Error::Timeout(_))));
+    }
+
+    #[test]
+    fn test_timeout_kills_process_group() {
+        let marker = std::env::temp_dir()
+            .join(format!("break-time-test-command-{}", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+
+        // The background subshell would create the marker after the timeout
+        // if only `sh` were killed.
+        let cmd = format!("(sleep 1.5; touch '{}') & wait", marker.display());
+        assert!(matches!(command(&cmd).run(), Err(Error::Timeout(_))));
+        std::thread::sleep(Duration::from_secs(1));
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn test_validate() {
+        let settings: Settings = toml::from_str(r#"cmd = "true""#).unwrap();
+        assert_eq!(settings.validate(), Ok(()));
+        let settings: Settings = toml::from_str(r#"cmd = " ""#).unwrap();
+        assert!(settings.validate().is_err());
+        let settings: Settings =
+            toml::from_str("cmd = \"true\"\ntimeout_seconds = 0").unwrap();
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn test_working_dir_and_env() {
+        let settings: Settings = toml::from_str(
+            r#"
+            name = "check env"
+            cmd = 'test "$(pwd)" = / && test "$FOO" = bar'
+            working_dir = "/"
+            env = { FOO = "bar" }
+            "#,
+        )
+        .unwrap();
+        let command = Command::new(&settings);
+        assert_eq!(command.name(), "check env");
+        assert!(command.run().unwrap().into_bool());
+    }
+}
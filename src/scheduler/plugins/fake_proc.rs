@@ -0,0 +1,40 @@
+// This module defines a made-up /proc directory for testing the plugins that read /proc, like
+// processes and capture_devices.  Each test adds the files its plugin needs to the process
+// directories.
+
+use std::path::PathBuf;
+
+/// A temporary directory that looks like /proc.  It is removed when this is
+/// dropped.
+pub struct FakeProc {
+    pub root: PathBuf,
+}
+
+impl FakeProc {
+    /// Create an empty directory.  `test_name` must be different for every
+    /// test, since tests run at the same time.
+    pub fn new(test_name: &str) -> Self {
+        let root = std::env::temp_dir().join(format!(
+            "break-time-test-proc-{}-{}",
+            test_name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        Self { root }
+    }
+
+    /// Add a process with the name `comm`, and return its directory.
+    pub fn add_process(&self, pid: u32, comm: &str) -> PathBuf {
+        let dir = self.root.join(pid.to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("comm"), format!("{}\n", comm)).unwrap();
+        dir
+    }
+}
+
+impl Drop for FakeProc {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
@@ -0,0 +1,230 @@
+// This module defines a plugin that stops breaks from happening while a fullscreen window is
+// open, like a presentation, a game, or a video.
+//
+// Window managers that follow the EWMH spec add `_NET_WM_STATE_FULLSCREEN` to the
+// `_NET_WM_STATE` property of fullscreen windows, and set `_NET_ACTIVE_WINDOW` on the root window
+// to the window that has focus.
+//
+// - https://specifications.freedesktop.org/wm-spec/latest/ar01s05.html#idm45805407959456
+
+use serde::{Deserialize, Serialize};
+
+use super::matcher::{Matcher, TextMatch};
+use super::{CanBreak, Plugin, PolicySettings};
+use crate::config::Config;
+use crate::x11::{WindowFinder, Windows, WmClass, X11};
+
+/// Settings for the `fullscreen` plugin.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_windows")]
+    pub windows: Windows,
+    /// Fullscreen windows with a class or instance name matching one of these
+    /// never stop a break.
+    #[serde(default)]
+    pub allow_classes: Vec<TextMatch>,
+    /// If this isn't empty, only fullscreen windows with a class or instance
+    /// name matching one of these stop a break.
+    #[serde(default)]
+    pub deny_classes: Vec<TextMatch>,
+    #[serde(default)]
+    pub policy: PolicySettings,
+}
+
+const fn default_windows() -> Windows {
+    Windows::Active
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            windows: default_windows(),
+            allow_classes: vec![],
+            deny_classes: vec![],
+            policy: PolicySettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Check that all the class patterns can be used.
+    pub fn validate(&self) -> Result<(), String> {
+        ClassRules::new(self).map(|_class_rules| ())
+    }
+}
+
+struct ClassRules {
+    allow: Vec<Matcher>,
+    deny: Vec<Matcher>,
+}
+
+impl ClassRules {
+    fn new(settings: &Settings) -> Result<Self, String> {
+        let compile = |text_matches: &[TextMatch]| {
+            text_matches
+                .iter()
+                .map(Matcher::new)
+                .collect::<Result<Vec<Matcher>, regex::Error>>()
+                .map_err(|err| {
+                    format!("could not compile regex in class list: {}", err)
+                })
+        };
+        Ok(Self {
+            allow: compile(&settings.allow_classes)?,
+            deny: compile(&settings.deny_classes)?,
+        })
+    }
+
+    /// Whether a fullscreen window with the given class should stop a break.
+    fn blocks_break(&self, opt_wm_class: Option<&WmClass>) -> bool {
+        let is_match = |matchers: &[Matcher]| {
+            opt_wm_class.map_or(false, |wm_class| {
+                matchers.iter().any(|matcher| {
+                    matcher.is_match(&wm_class.class)
+                        || matcher.is_match(&wm_class.instance)
+                })
+            })
+        };
+        !is_match(&self.allow) && (self.deny.is_empty() || is_match(&self.deny))
+    }
+}
+
+pub struct Fullscreen {
+    x11: X11,
+    window_finder: WindowFinder,
+    net_wm_state_atom: xcb::Atom,
+    net_wm_state_fullscreen_atom: xcb::Atom,
+    windows: Windows,
+    class_rules: ClassRules,
+}
+
+impl Fullscreen {
+    pub fn new(config: &Config) -> Result<Self, ()> {
+        let settings = &config.settings.all_plugin_settings.fullscreen;
+        let class_rules = ClassRules::new(settings).map_err(|err| {
+            println!("Could not set up fullscreen class lists: {}", err);
+        })?;
+
+        let x11 = X11::connect();
+
+        let window_finder = WindowFinder::new(&x11).ok_or(())?;
+        let net_wm_state_atom = x11.create_atom("_NET_WM_STATE").ok_or(())?;
+        let net_wm_state_fullscreen_atom =
+            x11.create_atom("_NET_WM_STATE_FULLSCREEN").ok_or(())?;
+
+        Ok(Self {
+            x11,
+            window_finder,
+            net_wm_state_atom,
+            net_wm_state_fullscreen_atom,
+            windows: settings.windows,
+            class_rules,
+        })
+    }
+
+    fn get_wins_to_check(&self) -> Result<Vec<xcb::Window>, ()> {
+        self.window_finder
+            .get_wins(&self.x11, self.windows)
+            .ok_or(())
+    }
+
+    fn is_fullscreen(&self, win: xcb::Window) -> bool {
+        self.x11
+            .get_win_atoms(win, self.net_wm_state_atom)
+            .contains(&self.net_wm_state_fullscreen_atom)
+    }
+
+    fn can_break(&self) -> Result<CanBreak, ()> {
+        for win in self.get_wins_to_check()? {
+            if !self.is_fullscreen(win) {
+                continue;
+            }
+            let opt_wm_class = self.x11.get_wm_class(win);
+            if self.class_rules.blocks_break(opt_wm_class.as_ref()) {
+                println!(
+                    "fullscreen: window {} with class {:?} is fullscreen",
+                    win, opt_wm_class
+                );
+                return Ok(CanBreak::No);
+            }
+        }
+        Ok(CanBreak::Yes)
+    }
+}
+
+impl Plugin for Fullscreen {
+    fn can_break_now(&self) -> Result<CanBreak, Box<dyn std::error::Error>> {
+        self.can_break().map_err(|()| {
+            Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "could not find the root window",
+            )) as Box<dyn std::error::Error>
+        })
+    }
+
+    fn name(&self) -> String {
+        String::from("fullscreen")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wm_class(instance: &str, class: &str) -> WmClass {
+        WmClass {
+            instance: String::from(instance),
+            class: String::from(class),
+        }
+    }
+
+    #[test]
+    fn test_class_rules() {
+        let settings: Settings = toml::from_str(
+            r#"
+            enabled = true
+            allow_classes = [{ exact = "Alacritty" }, { prefix = "mpv" }]
+            "#,
+        )
+        .unwrap();
+        let class_rules = ClassRules::new(&settings).unwrap();
+
+        let libreoffice = wm_class("libreoffice", "libreoffice-impress");
+        assert!(class_rules.blocks_break(Some(&libreoffice)));
+        assert!(class_rules.blocks_break(None));
+        assert!(!class_rules
+            .blocks_break(Some(&wm_class("Alacritty", "Alacritty"))));
+        assert!(!class_rules.blocks_break(Some(&wm_class("mpv", "mpv"))));
+
+        // With a deny list, only the classes on it stop a break.
+        let settings: Settings = toml::from_str(
+            r#"
+            enabled = true
+            deny_classes = [{ contains = "impress" }]
+            "#,
+        )
+        .unwrap();
+        let class_rules = ClassRules::new(&settings).unwrap();
+        assert!(class_rules.blocks_break(Some(&libreoffice)));
+        assert!(!class_rules.blocks_break(None));
+        assert!(!class_rules.blocks_break(Some(&wm_class("mpv", "mpv"))));
+    }
+
+    #[test]
+    fn test_windows() {
+        let settings: Settings = toml::from_str("").unwrap();
+        assert_eq!(settings.windows, Windows::Active);
+
+        // "all" is still accepted from before the window title checker and
+        // this plugin shared the same setting.
+        let settings: Settings = toml::from_str(r#"windows = "all""#).unwrap();
+        assert_eq!(settings.windows, Windows::ClientList);
+        let settings: Settings =
+            toml::from_str(r#"windows = "visible""#).unwrap();
+        assert_eq!(settings.windows, Windows::Visible);
+    }
+}
@@ -1,10 +1,13 @@
-use super::{CanBreak, Plugin};
+use super::{CanBreak, Plugin, PolicySettings};
 
-use crate::config::{Config, PluginSettings};
+use crate::config::Config;
 
 use std::collections::HashMap;
 use std::net::TcpListener;
 use std::path::Path;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
 
 use google_calendar3::{CalendarHub, CalendarListEntry, Events, Scope};
 use yup_oauth2::{
@@ -96,35 +99,16 @@ const GOOGLE_CLIENT_ID: &str =
 // https://stackoverflow.com/questions/59416326/safely-distribute-oauth-2-0-client-secret-in-desktop-applications-in-python
 const GOOGLE_CLIENT_SECRET: &str = "mI7MmEnboy8jdYEBjK9rZ2M2";
 
-// TODO: Create a datatype to hold all the settings for the GoogleCalendar plugin.
-// Don't try parsing it out manually here.
-#[allow(clippy::let_and_return)]
-fn get_emails(plugin_settings: &PluginSettings) -> Result<Vec<String>, ()> {
-    let google_cal_settings: &toml::Value =
-        match plugin_settings.0.get("google_calendar") {
-            // If the "google_calendar" key doesn't exist, then just skip.
-            None => return Ok(vec![]),
-            Some(val) => val,
-        };
-    let google_cal_settings_table: &toml::value::Table =
-        google_cal_settings.as_table().ok_or(
-            // If the "google_calendar" key exists, but it doesn't contain a table, then throw an
-            // error.
-            (),
-        )?;
-    let all_accounts: &toml::Value =
-        match google_cal_settings_table.get("accounts") {
-            // If the "google_calendar" key exists, but it doesn't have an accounts field, then
-            // just skip.
-            None => return Ok(vec![]),
-            Some(all_accounts) => all_accounts,
-        };
-
-    let all_emails = all_accounts.clone().try_into().map_err(|_err| ());
-
-    // println!("All emails: {:?}", all_emails);
-
-    all_emails
+/// Settings for the `google_calendar` plugin.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    /// The email addresses of the Google accounts whose calendars should be
+    /// checked.
+    #[serde(default)]
+    pub accounts: Vec<String>,
+    #[serde(default)]
+    pub policy: PolicySettings,
 }
 
 fn collect_first_err<T, E>(v: Vec<Result<T, E>>) -> Result<Vec<T>, E> {
@@ -141,13 +125,22 @@ fn collect_first_err<T, E>(v: Vec<Result<T, E>>) -> Result<Vec<T>, E> {
 }
 
 pub struct GoogleCalendar {
-    fetchers: Vec<CalFetcher>,
+    /// The `CalendarHub` in each fetcher uses `RefCell`s, so the fetchers are
+    /// behind a `Mutex` to let the plugin be checked from worker threads.  A
+    /// check takes them out while it talks to Google and puts them back
+    /// afterwards, so this is `None` while a check is running.
+    fetchers: Mutex<Option<Vec<CalFetcher>>>,
 }
 
 impl GoogleCalendar {
     pub fn new(config: &Config) -> Result<Self, ()> {
         let break_time_cache_dir: &Path = &config.cache_dir;
-        let emails = get_emails(&config.settings.all_plugin_settings)?;
+        let emails = config
+            .settings
+            .all_plugin_settings
+            .google_calendar
+            .accounts
+            .clone();
 
         let fetchers_res = emails
             .into_iter()
@@ -156,13 +149,26 @@ impl GoogleCalendar {
 
         let fetchers = collect_first_err(fetchers_res)?;
 
-        Ok(Self { fetchers })
+        Ok(Self {
+            fetchers: Mutex::new(Some(fetchers)),
+        })
     }
 
     fn can_break(&self) -> Result<CanBreak, GoogleCalErr> {
         // println!("now: {}, after twenty: {}", now.to_rfc3339(), in_twenty_mins.to_rfc3339());
 
-        self.fetchers.iter().map(CalFetcher::can_break).fold(
+        // The lock is only held while taking the fetchers out and putting
+        // them back.  A check that hangs on the network and is given up on
+        // by the plugin runner would otherwise keep every later check
+        // waiting on the lock.
+        let fetchers = self
+            .fetchers
+            .lock()
+            .expect("google calendar fetchers mutex poisoned")
+            .take()
+            .ok_or(GoogleCalErr::StillChecking)?;
+
+        let res = fetchers.iter().map(CalFetcher::can_break).fold(
             Ok(CanBreak::Yes),
             |accum, can_break_res| match (accum, can_break_res) {
                 (Err(err), _) => Err(err),
@@ -170,7 +176,14 @@ impl GoogleCalendar {
                 (Ok(CanBreak::No), _) => Ok(CanBreak::No),
                 (_, can_break) => can_break,
             },
-        )
+        );
+
+        *self
+            .fetchers
+            .lock()
+            .expect("google calendar fetchers mutex poisoned") = Some(fetchers);
+
+        res
     }
 }
 
@@ -284,6 +297,8 @@ pub enum GoogleCalErr {
         calendar_id: String,
         google_cal_err: google_calendar3::Error,
     },
+    /// A previous check is still waiting on Google.
+    StillChecking,
 }
 
 impl std::error::Error for GoogleCalErr {}
@@ -299,6 +314,10 @@ impl std::fmt::Display for GoogleCalErr {
                 "Google Calendard Plugin: Error fetching calendar_id {}: {}",
                 calendar_id, google_cal_err
             ),
+            Self::StillChecking => write!(
+                f,
+                "Google Calendar Plugin: the previous check of the calendars has not finished yet"
+            ),
         }
     }
 }
@@ -445,11 +464,13 @@ pub fn list_events(config: &Config) {
     let google_calendar = GoogleCalendar::new(config)
         .expect("Could not initialize Google Calendar.");
 
-    let event_calendar_lists: Vec<_> = google_calendar
+    let fetchers = google_calendar
         .fetchers
-        .iter()
-        .flat_map(get_events)
-        .collect();
+        .into_inner()
+        .expect("google calendar fetchers mutex poisoned")
+        .unwrap_or_default();
+    let event_calendar_lists: Vec<_> =
+        fetchers.iter().flat_map(get_events).collect();
 
     for (email, res_event_list) in event_calendar_lists {
         println!("{}:", email);
@@ -516,7 +537,12 @@ pub fn ignore_event(config: &Config, event_id: &str) {
         .expect("Could not initialize Google Calendar.");
 
     // let event_calendar_lists: Vec<_> = google_calendar.fetchers.iter().flat_map(get_events).collect();
-    for fetcher in google_calendar.fetchers {
+    let fetchers = google_calendar
+        .fetchers
+        .into_inner()
+        .expect("google calendar fetchers mutex poisoned")
+        .unwrap_or_default();
+    for fetcher in fetchers {
         for calendar_id in fetcher.calendar_ids {
             // We only check calendar_ids that are equal to the email address we are looking for.
             //
@@ -0,0 +1,989 @@
+// This module defines a plugin that stops breaks from happening during events in local
+// iCalendar (.ics) files, like the ones vdirsyncer syncs from a CalDAV server, or calendars
+// exported from another program.
+//
+// It works like the google_calendar plugin, but without needing the network: the files are read
+// every time the plugin is asked about a break, recurring events are expanded, and the events are
+// filtered with the same rules.
+//
+// Times with a TZID are converted to UTC with the VTIMEZONE of the same name in the calendar.  If
+// the calendar doesn't have one, the local time zone is used instead.
+//
+// - https://tools.ietf.org/html/rfc5545
+
+mod parse;
+mod rrule;
+
+use chrono::{
+    DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::command::expand_tilde;
+use super::{CanBreak, Plugin, PolicySettings};
+use crate::config::Config;
+use parse::{Component, Property};
+use rrule::RRule;
+
+/// Settings for the `ics` plugin.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    /// iCalendar files to check, or directories that are searched for `.ics`
+    /// files.  A leading `~` is replaced with the home directory.
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// Your email addresses, which are used to find the events you have
+    /// declined.
+    #[serde(default)]
+    pub emails: Vec<String>,
+    #[serde(default)]
+    pub policy: PolicySettings,
+}
+
+#[derive(Debug)]
+pub enum IcsErr {
+    Read(PathBuf, std::io::Error),
+}
+
+impl fmt::Display for IcsErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read(path, err) => {
+                write!(f, "could not read {}: {}", path.display(), err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for IcsErr {}
+
+/// A DATE or DATE-TIME value.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Time {
+    /// A whole day, in the local time zone.
+    Date(NaiveDate),
+    Utc(NaiveDateTime),
+    /// A time in the time zone with the given TZID, or in the local time
+    /// zone if there is no TZID.
+    Local {
+        time: NaiveDateTime,
+        tzid: Option<String>,
+    },
+}
+
+impl Time {
+    fn parse(prop: &Property) -> Result<Self, String> {
+        Self::parse_value(&prop.value, prop.param("TZID"))
+    }
+
+    fn parse_value(value: &str, tzid: Option<&str>) -> Result<Self, String> {
+        let parse_date_time = |value| {
+            NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+                .map_err(|err| format!("invalid time {}: {}", value, err))
+        };
+        if value.len() == 8 {
+            NaiveDate::parse_from_str(value, "%Y%m%d")
+                .map(Self::Date)
+                .map_err(|err| format!("invalid date {}: {}", value, err))
+        } else if let Some(utc) = value.strip_suffix('Z') {
+            parse_date_time(utc).map(Self::Utc)
+        } else {
+            Ok(Self::Local {
+                time: parse_date_time(value)?,
+                tzid: tzid.map(String::from),
+            })
+        }
+    }
+
+    fn naive(&self) -> NaiveDateTime {
+        match self {
+            Self::Date(date) => date.and_hms_opt(0, 0, 0).unwrap(),
+            Self::Utc(time) | Self::Local { time, .. } => *time,
+        }
+    }
+
+    /// The same kind of time, in the same time zone, but at `time`.
+    fn with_naive(&self, time: NaiveDateTime) -> Self {
+        match self {
+            Self::Date(_) => Self::Date(time.date()),
+            Self::Utc(_) => Self::Utc(time),
+            Self::Local { tzid, .. } => Self::Local {
+                time,
+                tzid: tzid.clone(),
+            },
+        }
+    }
+}
+
+/// Parse a UTC offset like `+0100` or `-053000`.
+fn parse_offset(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid UTC offset: {}", value);
+    let (sign, digits) = match value.split_at(value.len().min(1)) {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return Err(invalid()),
+    };
+    if !(digits.len() == 4 || digits.len() == 6)
+        || !digits.chars().all(|c| c.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+    let part = |range: std::ops::Range<usize>| {
+        digits
+            .get(range)
+            .map_or(0, |part| part.parse().unwrap_or(0))
+    };
+    Ok(Duration::seconds(
+        sign * (part(0..2) * 3600 + part(2..4) * 60 + part(4..6)),
+    ))
+}
+
+/// Parse a DURATION value like `PT1H30M` or `-P1D`.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration: {}", value);
+    let (sign, rest) = value
+        .strip_prefix('-')
+        .map_or_else(|| (1, value.trim_start_matches('+')), |rest| (-1, rest));
+    let rest = rest.strip_prefix('P').ok_or_else(invalid)?;
+
+    let mut seconds: i64 = 0;
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in rest.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        if c == 'T' && number.is_empty() {
+            in_time = true;
+            continue;
+        }
+        let unit = match (c, in_time) {
+            ('W', false) => 7 * 24 * 3600,
+            ('D', false) => 24 * 3600,
+            ('H', true) => 3600,
+            ('M', true) => 60,
+            ('S', true) => 1,
+            _ => return Err(invalid()),
+        };
+        seconds = number
+            .parse::<i64>()
+            .ok()
+            .and_then(|n| n.checked_mul(unit))
+            .and_then(|n| n.checked_add(seconds))
+            .ok_or_else(invalid)?;
+        number.clear();
+    }
+    if number.is_empty() {
+        Ok(Duration::seconds(sign * seconds))
+    } else {
+        Err(invalid())
+    }
+}
+
+/// A STANDARD or DAYLIGHT part of a VTIMEZONE, which says what the UTC
+/// offset is from the times it starts at.
+#[derive(Clone, Debug)]
+struct Observance {
+    /// The first time this observance starts, in the local time before it
+    /// starts.
+    dtstart: NaiveDateTime,
+    offset_from: Duration,
+    offset_to: Duration,
+    rrule: Option<RRule>,
+}
+
+impl Observance {
+    fn parse(component: &Component) -> Result<Self, String> {
+        let prop = |name| {
+            component
+                .property(name)
+                .ok_or_else(|| format!("{} without {}", component.name, name))
+        };
+        Ok(Self {
+            dtstart: Time::parse(prop("DTSTART")?)?.naive(),
+            offset_from: parse_offset(&prop("TZOFFSETFROM")?.value)?,
+            offset_to: parse_offset(&prop("TZOFFSETTO")?.value)?,
+            rrule: component
+                .property("RRULE")
+                .map(|prop| RRule::parse(&prop.value))
+                .transpose()?,
+        })
+    }
+
+    /// The last time this observance started, at or before `time`.
+    fn last_start(&self, time: NaiveDateTime) -> Option<NaiveDateTime> {
+        self.rrule.as_ref().map_or_else(
+            || Some(self.dtstart).filter(|dtstart| *dtstart <= time),
+            |rrule| {
+                rrule
+                    .occurrences(self.dtstart, time, |local| {
+                        Utc.from_utc_datetime(&(local - self.offset_from))
+                    })
+                    .last()
+                    .copied()
+            },
+        )
+    }
+}
+
+/// A VTIMEZONE.
+#[derive(Clone, Debug)]
+struct VTimeZone {
+    observances: Vec<Observance>,
+}
+
+impl VTimeZone {
+    fn parse(component: &Component) -> Result<(String, Self), String> {
+        let tzid = component
+            .property("TZID")
+            .ok_or_else(|| String::from("VTIMEZONE without TZID"))?
+            .value
+            .clone();
+        let observances = component
+            .components
+            .iter()
+            .filter(|sub| sub.name == "STANDARD" || sub.name == "DAYLIGHT")
+            .map(Observance::parse)
+            .collect::<Result<Vec<Observance>, String>>()?;
+        Ok((tzid, Self { observances }))
+    }
+
+    /// The UTC offset at a local time in this time zone.
+    fn offset_at(&self, time: NaiveDateTime) -> Duration {
+        self.observances
+            .iter()
+            .filter_map(|observance| {
+                observance
+                    .last_start(time)
+                    .map(|start| (start, observance.offset_to))
+            })
+            .max_by_key(|(start, _)| *start)
+            .map(|(_, offset)| offset)
+            .or_else(|| {
+                // Times before the first observance use the offset from
+                // before it.
+                self.observances
+                    .iter()
+                    .min_by_key(|observance| observance.dtstart)
+                    .map(|observance| observance.offset_from)
+            })
+            .unwrap_or_else(Duration::zero)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Attendee {
+    /// The email address, in lower case.
+    email: String,
+    /// The participation status, like `ACCEPTED` or `DECLINED`.
+    partstat: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+struct Event {
+    uid: Option<String>,
+    summary: String,
+    description: String,
+    status: Option<String>,
+    /// Whether `X-IGNORE-BREAK-TIME` is `TRUE`.
+    ignore_break_time: bool,
+    attendees: Vec<Attendee>,
+    start: Time,
+    end: Option<Time>,
+    duration: Option<Duration>,
+    rrule: Option<RRule>,
+    exdates: Vec<Time>,
+    /// The start of the instance of a recurring event that this event
+    /// replaces.
+    recurrence_id: Option<Time>,
+}
+
+impl Event {
+    fn parse(component: &Component) -> Result<Self, String> {
+        let text = |name| {
+            component
+                .property(name)
+                .map(|prop| parse::unescape_text(&prop.value))
+        };
+        let time = |name| component.property(name).map(Time::parse).transpose();
+
+        let attendees = component
+            .properties("ATTENDEE")
+            .map(|prop| Attendee {
+                email: prop
+                    .value
+                    .trim_start_matches("mailto:")
+                    .trim_start_matches("MAILTO:")
+                    .to_lowercase(),
+                partstat: prop.param("PARTSTAT").map(str::to_uppercase),
+            })
+            .collect();
+
+        let mut exdates = vec![];
+        for prop in component.properties("EXDATE") {
+            for value in prop.value.split(',') {
+                exdates.push(Time::parse_value(value, prop.param("TZID"))?);
+            }
+        }
+
+        // An RRULE that isn't supported still leaves the first instance, so
+        // that the event isn't dropped entirely.
+        let rrule = component.property("RRULE").and_then(|prop| {
+            RRule::parse(&prop.value)
+                .map_err(|err| {
+                    println!(
+                        "ics: ignoring RRULE of {:?}: {}",
+                        text("SUMMARY"),
+                        err
+                    );
+                })
+                .ok()
+        });
+
+        Ok(Self {
+            uid: text("UID"),
+            summary: text("SUMMARY").unwrap_or_default(),
+            description: text("DESCRIPTION").unwrap_or_default(),
+            status: text("STATUS").map(|status| status.to_uppercase()),
+            ignore_break_time: text("X-IGNORE-BREAK-TIME")
+                .map_or(false, |value| value.eq_ignore_ascii_case("true")),
+            attendees,
+            start: time("DTSTART")?
+                .ok_or_else(|| String::from("VEVENT without DTSTART"))?,
+            end: time("DTEND")?,
+            duration: component
+                .property("DURATION")
+                .map(|prop| parse_duration(&prop.value))
+                .transpose()?,
+            rrule,
+            exdates,
+            recurrence_id: time("RECURRENCE-ID")?,
+        })
+    }
+}
+
+/// Whether an event should stop a break.  These are the same rules as
+/// `filter_event` in the `google_calendar` plugin.
+fn filter_event(event: &Event, emails: &[String]) -> bool {
+    // Ignore events where the description contains the magic string
+    // "ignore break-time", or talks about being an out-of-office event.
+    let description = event.description.to_lowercase();
+    if description.contains("ignore break-time")
+        || description.contains("out-of-office event")
+    {
+        return false;
+    }
+
+    // Ignore events where the summary (title) is "Out of office".
+    if event.summary.to_lowercase() == "out of office" {
+        return false;
+    }
+
+    // Ignore events with `X-IGNORE-BREAK-TIME:TRUE`, which is the iCalendar
+    // version of the `ignore-break-time` extended property.
+    if event.ignore_break_time {
+        return false;
+    }
+
+    if event.status.as_deref() == Some("CANCELLED") {
+        return false;
+    }
+
+    // Ignore events you have declined.
+    let is_me = |attendee: &&Attendee| {
+        emails
+            .iter()
+            .any(|email| email.to_lowercase() == attendee.email)
+    };
+    if event
+        .attendees
+        .iter()
+        .filter(is_me)
+        .any(|attendee| attendee.partstat.as_deref() == Some("DECLINED"))
+    {
+        return false;
+    }
+
+    true
+}
+
+/// The events in a VCALENDAR, with the time zones they use.
+struct Calendar {
+    time_zones: HashMap<String, VTimeZone>,
+    events: Vec<Event>,
+}
+
+impl Calendar {
+    /// Parse a VCALENDAR.  Events and time zones that can't be understood
+    /// are skipped, so that one strange event doesn't hide all the others.
+    fn parse(component: &Component) -> Self {
+        let mut time_zones = HashMap::new();
+        for sub in component.components("VTIMEZONE") {
+            match VTimeZone::parse(sub) {
+                Err(err) => println!("ics: skipping time zone: {}", err),
+                Ok((tzid, time_zone)) => {
+                    time_zones.insert(tzid, time_zone);
+                }
+            }
+        }
+
+        let mut events = vec![];
+        for sub in component.components("VEVENT") {
+            match Event::parse(sub) {
+                Err(err) => println!(
+                    "ics: skipping event {:?}: {}",
+                    sub.property("SUMMARY").map(|prop| &prop.value),
+                    err
+                ),
+                Ok(event) => events.push(event),
+            }
+        }
+
+        Self { time_zones, events }
+    }
+
+    fn to_utc(&self, time: &Time) -> DateTime<Utc> {
+        match time {
+            Time::Utc(time) => Utc.from_utc_datetime(time),
+            Time::Local {
+                time,
+                tzid: Some(tzid),
+            } if self.time_zones.contains_key(tzid) => {
+                let offset = self.time_zones[tzid].offset_at(*time);
+                Utc.from_utc_datetime(&(*time - offset))
+            }
+            Time::Date(_) | Time::Local { .. } => {
+                let time = time.naive();
+                // This is only empty for times that are skipped when the
+                // clocks go forward.
+                Local.from_local_datetime(&time).earliest().map_or_else(
+                    || Utc.from_utc_datetime(&time),
+                    |local| local.with_timezone(&Utc),
+                )
+            }
+        }
+    }
+
+    /// The start and end times of the instances of `event` that overlap the
+    /// time between `start` and `end`.
+    ///
+    /// `overridden` are the starts of instances that are replaced by other
+    /// events with a RECURRENCE-ID.
+    fn instances(
+        &self,
+        event: &Event,
+        overridden: &[DateTime<Utc>],
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        let first_start = self.to_utc(&event.start);
+        let duration = match (&event.end, event.duration) {
+            (Some(event_end), _) => self.to_utc(event_end) - first_start,
+            (None, Some(duration)) => duration,
+            (None, None) => match event.start {
+                Time::Date(_) => Duration::days(1),
+                _ => Duration::zero(),
+            },
+        };
+
+        let starts = event.rrule.as_ref().map_or_else(
+            || vec![first_start],
+            |rrule| {
+                // Local times are never more than 14 hours away from UTC, so
+                // only the occurrences between these can overlap.
+                let first =
+                    (start - duration - Duration::hours(14)).naive_utc();
+                let last = (end + Duration::hours(14)).naive_utc();
+                let exdates: Vec<DateTime<Utc>> = event
+                    .exdates
+                    .iter()
+                    .map(|time| self.to_utc(time))
+                    .collect();
+                rrule
+                    .occurrences(event.start.naive(), last, |time| {
+                        self.to_utc(&event.start.with_naive(time))
+                    })
+                    .into_iter()
+                    .filter(|time| *time >= first)
+                    .map(|time| self.to_utc(&event.start.with_naive(time)))
+                    .filter(|time| {
+                        !exdates.contains(time) && !overridden.contains(time)
+                    })
+                    .collect()
+            },
+        );
+
+        starts
+            .into_iter()
+            .map(|instance_start| (instance_start, instance_start + duration))
+            .filter(|(instance_start, instance_end)| {
+                *instance_start < end && *instance_end > start
+            })
+            .collect()
+    }
+
+    /// The events that stop a break and have an instance between `start`
+    /// and `end`.
+    fn events_between(
+        &self,
+        emails: &[String],
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Vec<&Event> {
+        let mut overridden: HashMap<&str, Vec<DateTime<Utc>>> = HashMap::new();
+        for event in &self.events {
+            if let (Some(uid), Some(recurrence_id)) =
+                (&event.uid, &event.recurrence_id)
+            {
+                overridden
+                    .entry(uid)
+                    .or_default()
+                    .push(self.to_utc(recurrence_id));
+            }
+        }
+
+        self.events
+            .iter()
+            .filter(|event| filter_event(event, emails))
+            .filter(|event| {
+                let event_overridden = event
+                    .uid
+                    .as_deref()
+                    .filter(|_| event.recurrence_id.is_none())
+                    .and_then(|uid| overridden.get(uid))
+                    .map_or(&[][..], |starts| &starts[..]);
+                !self
+                    .instances(event, event_overridden, start, end)
+                    .is_empty()
+            })
+            .collect()
+    }
+}
+
+/// The iCalendar files at `path`.  If `path` is a directory, this is all the
+/// `.ics` files in it and its subdirectories, sorted by path.
+fn ics_files(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = vec![];
+    for entry in std::fs::read_dir(path)? {
+        let entry_path = entry?.path();
+        if entry_path.is_dir() {
+            files.extend(ics_files(&entry_path)?);
+        } else if entry_path.extension().map_or(false, |ext| ext == "ics") {
+            files.push(entry_path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Read the calendars at `path`.  Files that can't be read or parsed are
+/// skipped, so that one broken file doesn't hide the events in the others.
+fn read_calendars(path: &Path) -> Result<Vec<Calendar>, IcsErr> {
+    let mut calendars = vec![];
+    for file in ics_files(path).map_err(|err| IcsErr::Read(path.into(), err))? {
+        let components = match std::fs::read_to_string(&file)
+            .map_err(|err| err.to_string())
+            .and_then(|text| parse::parse(&text))
+        {
+            Err(err) => {
+                println!("ics: skipping {}: {}", file.display(), err);
+                continue;
+            }
+            Ok(components) => components,
+        };
+        calendars.extend(
+            components
+                .iter()
+                .filter(|component| component.name == "VCALENDAR")
+                .map(Calendar::parse),
+        );
+    }
+    Ok(calendars)
+}
+
+pub struct Ics {
+    paths: Vec<PathBuf>,
+    emails: Vec<String>,
+}
+
+impl Ics {
+    pub fn new(config: &Config) -> Self {
+        let settings = &config.settings.all_plugin_settings.ics;
+        Self {
+            paths: settings
+                .paths
+                .iter()
+                .map(|path| expand_tilde(path))
+                .collect(),
+            emails: settings.emails.clone(),
+        }
+    }
+
+    /// Whether there is an event that stops a break between `start` and
+    /// `end`.
+    fn has_event_between(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<bool, IcsErr> {
+        let mut has_event = false;
+        for path in &self.paths {
+            for calendar in read_calendars(path)? {
+                for event in calendar.events_between(&self.emails, start, end) {
+                    println!(
+                        "ics: event \"{}\" in {} is happening",
+                        event.summary,
+                        path.display()
+                    );
+                    has_event = true;
+                }
+            }
+        }
+        Ok(has_event)
+    }
+
+    fn can_break(&self) -> Result<CanBreak, IcsErr> {
+        // This is the same time window that the google_calendar plugin uses.
+        let now = Utc::now();
+        let ten_minutes_ago = now - Duration::minutes(10);
+        let in_twenty_mins = now + Duration::minutes(20);
+        self.has_event_between(ten_minutes_ago, in_twenty_mins)
+            .map(|has_event| CanBreak::from_bool(!has_event))
+    }
+}
+
+impl Plugin for Ics {
+    fn can_break_now(&self) -> Result<CanBreak, Box<dyn std::error::Error>> {
+        self.can_break()
+            .map_err(|err| Box::new(err) as Box<dyn std::error::Error>)
+    }
+
+    fn name(&self) -> String {
+        String::from("ics")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR
+VERSION:2.0
+PRODID:-//test//EN
+BEGIN:VTIMEZONE
+TZID:Europe/Berlin
+BEGIN:DAYLIGHT
+TZOFFSETFROM:+0100
+TZOFFSETTO:+0200
+DTSTART:19810329T020000
+RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU
+END:DAYLIGHT
+BEGIN:STANDARD
+TZOFFSETFROM:+0200
+TZOFFSETTO:+0100
+DTSTART:19961027T030000
+RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU
+END:STANDARD
+END:VTIMEZONE
+BEGIN:VEVENT
+UID:standup
+SUMMARY:Standup
+DTSTART;TZID=Europe/Berlin:20200106T093000
+DURATION:PT15M
+RRULE:FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR
+EXDATE;TZID=Europe/Berlin:20200107T093000
+END:VEVENT
+BEGIN:VEVENT
+UID:standup
+RECURRENCE-ID;TZID=Europe/Berlin:20200108T093000
+SUMMARY:Standup
+DTSTART;TZID=Europe/Berlin:20200108T110000
+DTEND;TZID=Europe/Berlin:20200108T111500
+END:VEVENT
+BEGIN:VEVENT
+UID:lunch
+SUMMARY:Lunch
+DESCRIPTION:Ignore break-time\\, we are eating anyway
+DTSTART:20200106T120000Z
+DTEND:20200106T130000Z
+END:VEVENT
+BEGIN:VEVENT
+UID:review
+SUMMARY:Review
+DTSTART:20200106T150000Z
+DTEND:20200106T160000Z
+ATTENDEE;PARTSTAT=ACCEPTED:mailto:boss@example.com
+ATTENDEE;PARTSTAT=DECLINED:mailto:Me@Example.com
+END:VEVENT
+BEGIN:VEVENT
+UID:retro
+SUMMARY:Retro
+DTSTART:20200106T170000Z
+DTEND:20200106T173000Z
+RRULE:FREQ=HOURLY
+END:VEVENT
+END:VCALENDAR
+";
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        Utc.from_utc_datetime(
+            &NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap(),
+        )
+    }
+
+    fn calendar() -> Calendar {
+        Calendar::parse(&parse::parse(CALENDAR).unwrap()[0])
+    }
+
+    fn summaries_between(
+        calendar: &Calendar,
+        emails: &[String],
+        start: &str,
+        end: &str,
+    ) -> Vec<String> {
+        calendar
+            .events_between(emails, utc(start), utc(end))
+            .into_iter()
+            .map(|event| event.summary.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_values() {
+        assert_eq!(parse_offset("+0100"), Ok(Duration::hours(1)));
+        assert_eq!(
+            parse_offset("-053000"),
+            Ok(-(Duration::hours(5) + Duration::minutes(30)))
+        );
+        assert!(parse_offset("0100").is_err());
+
+        assert_eq!(
+            parse_duration("PT1H30M"),
+            Ok(Duration::hours(1) + Duration::minutes(30))
+        );
+        assert_eq!(parse_duration("-P1W"), Ok(-Duration::weeks(1)));
+        assert_eq!(
+            parse_duration("P1DT12H"),
+            Ok(Duration::days(1) + Duration::hours(12))
+        );
+        assert!(parse_duration("P1M").is_err());
+        assert!(parse_duration("PT1").is_err());
+    }
+
+    #[test]
+    fn test_time_zone() {
+        let calendar = calendar();
+        let berlin = |time: &str| Time::Local {
+            time: NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M")
+                .unwrap(),
+            tzid: Some(String::from("Europe/Berlin")),
+        };
+        assert_eq!(
+            calendar.to_utc(&berlin("2020-01-06 09:30")),
+            utc("2020-01-06 08:30")
+        );
+        assert_eq!(
+            calendar.to_utc(&berlin("2020-07-06 09:30")),
+            utc("2020-07-06 07:30")
+        );
+        assert_eq!(
+            calendar.to_utc(&berlin("2020-10-25 09:30")),
+            utc("2020-10-25 08:30")
+        );
+    }
+
+    #[test]
+    fn test_events_between() {
+        let calendar = calendar();
+        let no_emails: Vec<String> = vec![];
+
+        // The first standup, with the same window as the plugin uses.
+        assert_eq!(
+            summaries_between(
+                &calendar,
+                &no_emails,
+                "2020-01-06 08:10",
+                "2020-01-06 08:40"
+            ),
+            vec!["Standup"]
+        );
+        // The standup is over by 8:45 UTC.
+        assert!(summaries_between(
+            &calendar,
+            &no_emails,
+            "2020-01-06 08:45",
+            "2020-01-06 09:15"
+        )
+        .is_empty());
+        // The standup on the 7th is excluded.
+        assert!(summaries_between(
+            &calendar,
+            &no_emails,
+            "2020-01-07 08:10",
+            "2020-01-07 08:40"
+        )
+        .is_empty());
+        // The standup on the 8th has been moved to 11:00.
+        assert!(summaries_between(
+            &calendar,
+            &no_emails,
+            "2020-01-08 08:10",
+            "2020-01-08 08:40"
+        )
+        .is_empty());
+        assert_eq!(
+            summaries_between(
+                &calendar,
+                &no_emails,
+                "2020-01-08 09:50",
+                "2020-01-08 10:20"
+            ),
+            vec!["Standup"]
+        );
+        // In the summer, the standup is an hour earlier in UTC.
+        assert_eq!(
+            summaries_between(
+                &calendar,
+                &no_emails,
+                "2020-07-06 07:10",
+                "2020-07-06 07:40"
+            ),
+            vec!["Standup"]
+        );
+        // No standups on weekends.
+        assert!(summaries_between(
+            &calendar,
+            &no_emails,
+            "2020-07-04 07:10",
+            "2020-07-04 07:40"
+        )
+        .is_empty());
+
+        // Lunch says to ignore break-time.
+        assert!(summaries_between(
+            &calendar,
+            &no_emails,
+            "2020-01-06 12:10",
+            "2020-01-06 12:40"
+        )
+        .is_empty());
+
+        // The review only counts until it is known to be declined.
+        assert_eq!(
+            summaries_between(
+                &calendar,
+                &no_emails,
+                "2020-01-06 15:10",
+                "2020-01-06 15:40"
+            ),
+            vec!["Review"]
+        );
+        assert!(summaries_between(
+            &calendar,
+            &[String::from("me@example.com")],
+            "2020-01-06 15:10",
+            "2020-01-06 15:40"
+        )
+        .is_empty());
+
+        // The retro has an RRULE that isn't supported, so only its first
+        // instance is used.
+        assert_eq!(
+            summaries_between(
+                &calendar,
+                &no_emails,
+                "2020-01-06 16:50",
+                "2020-01-06 17:20"
+            ),
+            vec!["Retro"]
+        );
+        assert!(summaries_between(
+            &calendar,
+            &no_emails,
+            "2020-01-06 17:50",
+            "2020-01-06 18:20"
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_filter_event() {
+        let event = || {
+            Event::parse(
+                &parse::parse(
+                    "BEGIN:VEVENT\nSUMMARY:Meeting\nDTSTART:20200106T120000Z\nEND:VEVENT\n",
+                )
+                .unwrap()[0],
+            )
+            .unwrap()
+        };
+        assert!(filter_event(&event(), &[]));
+
+        let mut out_of_office = event();
+        out_of_office.summary = String::from("Out of office");
+        assert!(!filter_event(&out_of_office, &[]));
+
+        let mut out_of_office = event();
+        out_of_office.description =
+            String::from("This is an out-of-office event.");
+        assert!(!filter_event(&out_of_office, &[]));
+
+        let mut cancelled = event();
+        cancelled.status = Some(String::from("CANCELLED"));
+        assert!(!filter_event(&cancelled, &[]));
+
+        let mut ignored = event();
+        ignored.ignore_break_time = true;
+        assert!(!filter_event(&ignored, &[]));
+    }
+
+    #[test]
+    fn test_read_directory() {
+        let dir = std::env::temp_dir()
+            .join(format!("break-time-test-ics-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("work")).unwrap();
+        std::fs::write(dir.join("work/events.ics"), CALENDAR).unwrap();
+        std::fs::write(dir.join("work/displayname"), "Work").unwrap();
+
+        let plugin = Ics {
+            paths: vec![dir.clone()],
+            emails: vec![],
+        };
+        assert!(plugin
+            .has_event_between(utc("2020-01-06 08:10"), utc("2020-01-06 08:40"))
+            .unwrap());
+        assert!(!plugin
+            .has_event_between(utc("2020-01-06 10:10"), utc("2020-01-06 10:40"))
+            .unwrap());
+
+        std::fs::write(dir.join("work/broken.ics"), "BEGIN:VCALENDAR\n")
+            .unwrap();
+        // A broken file is skipped, and the events in the others still count.
+        assert!(plugin
+            .has_event_between(utc("2020-01-06 08:10"), utc("2020-01-06 08:40"))
+            .unwrap());
+        assert!(!plugin
+            .has_event_between(utc("2020-01-06 10:10"), utc("2020-01-06 10:40"))
+            .unwrap());
+
+        // So is a file that doesn't exist.
+        let missing = Ics {
+            paths: vec![dir.join("missing.ics"), dir.clone()],
+            emails: vec![],
+        };
+        assert!(missing
+            .has_event_between(utc("2020-01-06 08:10"), utc("2020-01-06 08:40"))
+            .unwrap());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,240 @@
+// This module parses the iCalendar format into components and properties, without knowing
+// anything about what they mean.
+//
+// An iCalendar file is made up of content lines like `DTSTART;TZID=Europe/Berlin:20200110T090000`,
+// which is a property name, some parameters, and a value.  Components are the lines between
+// `BEGIN:VEVENT` and `END:VEVENT`, and can be nested.  Long lines are folded by putting a line
+// break followed by a space or a tab in the middle of them.
+//
+// - https://tools.ietf.org/html/rfc5545#section-3.1
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Property {
+    /// The name of the property, in upper case.
+    pub name: String,
+    /// The parameters, with the names in upper case and quotes removed from
+    /// the values.
+    pub params: Vec<(String, String)>,
+    pub value: String,
+}
+
+impl Property {
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param_name, _)| param_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Component {
+    /// The name of the component, like `VEVENT`, in upper case.
+    pub name: String,
+    pub properties: Vec<Property>,
+    pub components: Vec<Self>,
+}
+
+impl Component {
+    /// The first property called `name`.
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.iter().find(|prop| prop.name == name)
+    }
+
+    /// All the properties called `name`.
+    pub fn properties<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a Property> + 'a {
+        self.properties.iter().filter(move |prop| prop.name == name)
+    }
+
+    /// All the sub-components called `name`.
+    pub fn components<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a Self> + 'a {
+        self.components
+            .iter()
+            .filter(move |component| component.name == name)
+    }
+}
+
+/// Join folded lines back together.
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for line in text.lines() {
+        let line = line.trim_end_matches('\r');
+        match (
+            line.strip_prefix(|c| c == ' ' || c == '\t'),
+            lines.last_mut(),
+        ) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(String::from(line)),
+        }
+    }
+    lines
+}
+
+/// Split `text` on `separator`, except where it is between double quotes.
+fn split_unquoted(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut in_quotes = false;
+    let mut part_start = 0;
+    for (i, c) in text.char_indices() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        } else if c == separator && !in_quotes {
+            parts.push(&text[part_start..i]);
+            part_start = i + 1;
+        }
+    }
+    parts.push(&text[part_start..]);
+    parts
+}
+
+fn parse_line(line: &str) -> Result<Property, String> {
+    // The value starts after the first colon that isn't in a quoted
+    // parameter value.
+    let mut in_quotes = false;
+    let colon = line
+        .char_indices()
+        .find(|(_, c)| {
+            if *c == '"' {
+                in_quotes = !in_quotes;
+            }
+            *c == ':' && !in_quotes
+        })
+        .map(|(i, _)| i)
+        .ok_or_else(|| format!("line without a colon: {}", line))?;
+
+    let head = split_unquoted(&line[..colon], ';');
+    let params = head[1..]
+        .iter()
+        .map(|param| {
+            let (name, value) = param.split_once('=').ok_or_else(|| {
+                format!("parameter without a value: {}", param)
+            })?;
+            Ok((name.to_uppercase(), value.trim_matches('"').to_string()))
+        })
+        .collect::<Result<Vec<(String, String)>, String>>()?;
+
+    Ok(Property {
+        name: head[0].to_uppercase(),
+        params,
+        value: line[colon + 1..].to_string(),
+    })
+}
+
+/// Parse the contents of an iCalendar file.  This returns the top-level
+/// components, which are normally `VCALENDAR`s.
+pub fn parse(text: &str) -> Result<Vec<Component>, String> {
+    // The bottom of the stack holds the top-level components.
+    let mut stack = vec![Component::default()];
+    for line in unfold(text) {
+        if line.is_empty() {
+            continue;
+        }
+        let prop = parse_line(&line)?;
+        match prop.name.as_str() {
+            "BEGIN" => stack.push(Component {
+                name: prop.value.to_uppercase(),
+                ..Component::default()
+            }),
+            "END" => {
+                let component = stack
+                    .pop()
+                    .filter(|_| !stack.is_empty())
+                    .ok_or_else(|| format!("unexpected END:{}", prop.value))?;
+                if component.name != prop.value.to_uppercase() {
+                    return Err(format!(
+                        "expected END:{}, but got END:{}",
+                        component.name, prop.value
+                    ));
+                }
+                // There is always at least the bottom of the stack left.
+                stack.last_mut().unwrap().components.push(component);
+            }
+            _ => {
+                // Properties outside of any component are ignored.
+                if stack.len() > 1 {
+                    stack.last_mut().unwrap().properties.push(prop);
+                }
+            }
+        }
+    }
+
+    if stack.len() == 1 {
+        Ok(stack.pop().unwrap().components)
+    } else {
+        Err(format!("missing END:{}", stack.last().unwrap().name))
+    }
+}
+
+/// Undo the escaping of a TEXT value, like `SUMMARY` or `DESCRIPTION`.
+pub fn unescape_text(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => text.push('\n'),
+                Some(escaped) => text.push(escaped),
+                None => text.push('\\'),
+            }
+        } else {
+            text.push(c);
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let components = parse(
+            "BEGIN:VCALENDAR\r\n\
+             VERSION:2.0\r\n\
+             BEGIN:VEVENT\r\n\
+             SUMMARY:Planning\\, part 2\r\n\
+             DESCRIPTION:This is a long description that has been folded on\r\n \
+             to the next line\r\n\
+             ATTENDEE;CN=\"Doe; Jane\";PARTSTAT=DECLINED:mailto:jane@example.com\r\n\
+             END:VEVENT\r\n\
+             END:VCALENDAR\r\n",
+        )
+        .unwrap();
+
+        assert_eq!(components.len(), 1);
+        let calendar = &components[0];
+        assert_eq!(calendar.name, "VCALENDAR");
+        assert_eq!(calendar.property("VERSION").unwrap().value, "2.0");
+
+        let event = calendar.components("VEVENT").next().unwrap();
+        assert_eq!(
+            unescape_text(&event.property("SUMMARY").unwrap().value),
+            "Planning, part 2"
+        );
+        assert_eq!(
+            event.property("DESCRIPTION").unwrap().value,
+            "This is a long description that has been folded onto the next line"
+        );
+        let attendee = event.property("ATTENDEE").unwrap();
+        assert_eq!(attendee.param("CN"), Some("Doe; Jane"));
+        assert_eq!(attendee.param("PARTSTAT"), Some("DECLINED"));
+        assert_eq!(attendee.value, "mailto:jane@example.com");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(
+            parse("BEGIN:VCALENDAR\nBEGIN:VEVENT\nEND:VCALENDAR\n").is_err()
+        );
+        assert!(parse("BEGIN:VCALENDAR\n").is_err());
+        assert!(parse("END:VCALENDAR\n").is_err());
+        assert!(parse("BEGIN:VCALENDAR\nno colon\nEND:VCALENDAR\n").is_err());
+    }
+}
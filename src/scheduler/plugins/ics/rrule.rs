@@ -0,0 +1,599 @@
+// This module expands the recurrence rules (RRULE) of iCalendar events into the times the events
+// happen at.
+//
+// A rule like `FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,WE` is expanded by walking through the periods of
+// the rule (every second week here), and picking the days in each period that match the BY*
+// parts.  Only the parts that calendar programs commonly use are supported: FREQ (DAILY, WEEKLY,
+// MONTHLY, or YEARLY), INTERVAL, COUNT, UNTIL, BYDAY, BYMONTHDAY, BYMONTH, and WKST (which is
+// ignored).
+//
+// All the times here are local times in the time zone of the event.
+//
+// - https://tools.ietf.org/html/rfc5545#section-3.3.10
+
+use chrono::{
+    DateTime, Datelike, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday,
+};
+
+use crate::prelude::*;
+
+/// Stop looking for occurrences after this many periods, so that a rule that
+/// never matches anything doesn't loop forever.
+const MAX_PERIODS: i64 = 100_000;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Until {
+    /// The last occurrence can start at this time in UTC.
+    Utc(DateTime<Utc>),
+    /// The last occurrence can start at this local time.
+    Local(NaiveDateTime),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RRule {
+    pub freq: Frequency,
+    pub interval: u32,
+    pub count: Option<u32>,
+    pub until: Option<Until>,
+    /// Weekdays, with an optional ordinal like the `-1` in `-1FR` for the
+    /// last Friday of the month.
+    pub by_day: Vec<(Option<i32>, Weekday)>,
+    /// Days of the month, where negative numbers count from the end.
+    pub by_month_day: Vec<i32>,
+    pub by_month: Vec<u32>,
+}
+
+fn parse_weekday(text: &str) -> Result<Weekday, String> {
+    match text {
+        "MO" => Ok(Weekday::Mon),
+        "TU" => Ok(Weekday::Tue),
+        "WE" => Ok(Weekday::Wed),
+        "TH" => Ok(Weekday::Thu),
+        "FR" => Ok(Weekday::Fri),
+        "SA" => Ok(Weekday::Sat),
+        "SU" => Ok(Weekday::Sun),
+        _ => Err(format!("invalid weekday: {}", text)),
+    }
+}
+
+fn parse_by_day(text: &str) -> Result<(Option<i32>, Weekday), String> {
+    if text.len() < 2 || !text.is_char_boundary(text.len() - 2) {
+        return Err(format!("invalid BYDAY: {}", text));
+    }
+    let (ordinal, weekday) = text.split_at(text.len() - 2);
+    let ordinal = if ordinal.is_empty() {
+        None
+    } else {
+        Some(
+            ordinal
+                .parse()
+                .map_err(|_| format!("invalid BYDAY: {}", text))?,
+        )
+    };
+    Ok((ordinal, parse_weekday(weekday)?))
+}
+
+fn parse_list<T: std::str::FromStr>(
+    name: &str,
+    value: &str,
+) -> Result<Vec<T>, String> {
+    value
+        .split(',')
+        .map(|item| {
+            item.parse()
+                .map_err(|_| format!("invalid {}: {}", name, value))
+        })
+        .collect()
+}
+
+fn parse_until(value: &str) -> Result<Until, String> {
+    let parse_date_time =
+        |value| NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S");
+    let until = match (value.strip_suffix('Z'), value.len()) {
+        (Some(utc), _) => parse_date_time(utc)
+            .map(|naive| Until::Utc(Utc.from_utc_datetime(&naive))),
+        (None, 8) => NaiveDate::parse_from_str(value, "%Y%m%d")
+            .map(|date| Until::Local(date.and_hms_opt(23, 59, 59).unwrap())),
+        (None, _) => parse_date_time(value).map(Until::Local),
+    };
+    until.map_err(|err| format!("invalid UNTIL {}: {}", value, err))
+}
+
+impl RRule {
+    /// Parse the value of an RRULE property.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut freq = None;
+        let mut rrule = Self {
+            freq: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: vec![],
+            by_month_day: vec![],
+            by_month: vec![],
+        };
+        for part in value.split(';').filter(|part| !part.is_empty()) {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("invalid RRULE part: {}", part))?;
+            match name.to_uppercase().as_str() {
+                "FREQ" => {
+                    freq = Some(match value.to_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => {
+                            return Err(format!("unsupported FREQ: {}", value))
+                        }
+                    });
+                }
+                "INTERVAL" => {
+                    rrule.interval = value
+                        .parse()
+                        .ok()
+                        .filter(|interval| *interval > 0)
+                        .ok_or_else(|| {
+                            format!("invalid INTERVAL: {}", value)
+                        })?;
+                }
+                "COUNT" => {
+                    rrule.count =
+                        Some(value.parse().map_err(|_| {
+                            format!("invalid COUNT: {}", value)
+                        })?);
+                }
+                "UNTIL" => rrule.until = Some(parse_until(value)?),
+                "BYDAY" => {
+                    rrule.by_day = value
+                        .to_uppercase()
+                        .split(',')
+                        .map(parse_by_day)
+                        .collect::<Result<_, _>>()?;
+                }
+                "BYMONTHDAY" => {
+                    rrule.by_month_day = parse_list("BYMONTHDAY", value)?;
+                }
+                "BYMONTH" => rrule.by_month = parse_list("BYMONTH", value)?,
+                "WKST" => {}
+                _ => return Err(format!("unsupported RRULE part: {}", part)),
+            }
+        }
+        rrule.freq = freq.ok_or_else(|| String::from("RRULE without FREQ"))?;
+
+        // Ordinals in BYDAY count weekdays in the whole year when there is no
+        // BYMONTH, which isn't supported.
+        if rrule.freq == Frequency::Yearly
+            && rrule.by_month.is_empty()
+            && !rrule.by_day.is_empty()
+        {
+            return Err(String::from(
+                "unsupported RRULE: YEARLY BYDAY without BYMONTH",
+            ));
+        }
+        Ok(rrule)
+    }
+
+    /// All the times the event happens at, starting from `dtstart` and up to
+    /// and including `end`.
+    ///
+    /// `to_utc` converts the local times to UTC, which is needed when `UNTIL`
+    /// is in UTC.
+    pub fn occurrences(
+        &self,
+        dtstart: NaiveDateTime,
+        end: NaiveDateTime,
+        to_utc: impl Fn(NaiveDateTime) -> DateTime<Utc>,
+    ) -> Vec<NaiveDateTime> {
+        let is_after_until = |time: NaiveDateTime| match self.until {
+            None => false,
+            Some(Until::Utc(until)) => to_utc(time) > until,
+            Some(Until::Local(until)) => time > until,
+        };
+        let is_over = |occurrences: &[NaiveDateTime], time: NaiveDateTime| {
+            time > end
+                || is_after_until(time)
+                || self
+                    .count
+                    .map_or(false, |count| occurrences.len() >= count as usize)
+        };
+
+        // The start always counts as the first occurrence, even if it doesn't
+        // match the rule.
+        let mut occurrences = vec![];
+        if is_over(&occurrences, dtstart) {
+            return occurrences;
+        }
+        occurrences.push(dtstart);
+
+        for period in 0..MAX_PERIODS {
+            // This is empty once the dates are too far away for chrono.
+            let (period_start, dates) =
+                match self.period_dates(dtstart.date(), period) {
+                    None => break,
+                    Some(period_dates) => period_dates,
+                };
+            if period_start > end.date() {
+                break;
+            }
+            for date in dates {
+                let time = date.and_time(dtstart.time());
+                if time <= dtstart {
+                    continue;
+                }
+                if is_over(&occurrences, time) {
+                    return occurrences;
+                }
+                occurrences.push(time);
+            }
+        }
+        occurrences
+    }
+
+    /// The first day of the `period`th period of the rule, and the dates in
+    /// it that match the rule, in order.
+    fn period_dates(
+        &self,
+        start: NaiveDate,
+        period: i64,
+    ) -> Option<(NaiveDate, Vec<NaiveDate>)> {
+        let step = period.checked_mul(i64::from(self.interval))?;
+        match self.freq {
+            Frequency::Daily => {
+                let date = add_days(start, step)?;
+                let matches = self.matches_month(date.month())
+                    && self.matches_month_day(date)
+                    && (self.by_day.is_empty()
+                        || self
+                            .by_day
+                            .iter()
+                            .any(|(_, weekday)| *weekday == date.weekday()));
+                Some((date, if matches { vec![date] } else { vec![] }))
+            }
+            Frequency::Weekly => {
+                let week_start = add_days(
+                    start,
+                    step.checked_mul(7)?
+                        - i64::from(start.weekday().num_days_from_monday()),
+                )?;
+                let mut dates: Vec<NaiveDate> = if self.by_day.is_empty() {
+                    vec![start.weekday()]
+                } else {
+                    self.by_day.iter().map(|(_, weekday)| *weekday).collect()
+                }
+                .into_iter()
+                .filter_map(|weekday| {
+                    add_days(
+                        week_start,
+                        i64::from(weekday.num_days_from_monday()),
+                    )
+                })
+                .filter(|date| self.matches_month(date.month()))
+                .collect();
+                dates.sort();
+                dates.dedup();
+                Some((week_start, dates))
+            }
+            Frequency::Monthly => {
+                let months = i64::from(start.month0()).checked_add(step)?;
+                let year = i32::try_from(
+                    i64::from(start.year()) + months.div_euclid(12),
+                )
+                .ok()?;
+                let month = u32::try_from(months.rem_euclid(12)).ok()? + 1;
+                let dates = if self.matches_month(month) {
+                    self.month_dates(year, month, start.day())
+                } else {
+                    vec![]
+                };
+                Some((NaiveDate::from_ymd_opt(year, month, 1)?, dates))
+            }
+            Frequency::Yearly => {
+                let year =
+                    i32::try_from(i64::from(start.year()).checked_add(step)?)
+                        .ok()?;
+                let months = if self.by_month.is_empty() {
+                    vec![start.month()]
+                } else {
+                    let mut months = self.by_month.clone();
+                    months.sort_unstable();
+                    months
+                };
+                let dates = months
+                    .into_iter()
+                    .filter(|month| (1..=12).contains(month))
+                    .flat_map(|month| {
+                        self.month_dates(year, month, start.day())
+                    })
+                    .collect();
+                Some((NaiveDate::from_ymd_opt(year, 1, 1)?, dates))
+            }
+        }
+    }
+
+    fn matches_month(&self, month: u32) -> bool {
+        self.by_month.is_empty() || self.by_month.contains(&month)
+    }
+
+    fn matches_month_day(&self, date: NaiveDate) -> bool {
+        let days = days_in_month(date.year(), date.month());
+        self.by_month_day.is_empty()
+            || self
+                .by_month_day
+                .iter()
+                .any(|day| resolve_month_day(*day, days) == Some(date.day()))
+    }
+
+    /// The dates in a month that match BYDAY and BYMONTHDAY, or the same day
+    /// of the month as the start if there are neither.
+    fn month_dates(
+        &self,
+        year: i32,
+        month: u32,
+        start_day: u32,
+    ) -> Vec<NaiveDate> {
+        let days = days_in_month(year, month);
+        let mut month_days: Vec<u32> = if self.by_month_day.is_empty() {
+            if self.by_day.is_empty() {
+                vec![start_day]
+            } else {
+                (1..=days).collect()
+            }
+        } else {
+            self.by_month_day
+                .iter()
+                .filter_map(|day| resolve_month_day(*day, days))
+                .collect()
+        };
+
+        if !self.by_day.is_empty() {
+            let by_day_days: Vec<u32> = self
+                .by_day
+                .iter()
+                .flat_map(|(ordinal, weekday)| {
+                    weekdays_in_month(year, month, *weekday, *ordinal)
+                })
+                .collect();
+            month_days.retain(|day| by_day_days.contains(day));
+        }
+
+        month_days.sort_unstable();
+        month_days.dedup();
+        month_days
+            .into_iter()
+            .filter_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+            .collect()
+    }
+}
+
+/// `date` moved by `days` days, if that is a date chrono can represent.
+fn add_days(date: NaiveDate, days: i64) -> Option<NaiveDate> {
+    i64::from(date.num_days_from_ce())
+        .checked_add(days)
+        .and_then(|days| i32::try_from(days).ok())
+        .and_then(NaiveDate::from_num_days_from_ce_opt)
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    (28..=31)
+        .rev()
+        .find(|day| NaiveDate::from_ymd_opt(year, month, *day).is_some())
+        .unwrap_or(0)
+}
+
+/// Turn a day of the month from BYMONTHDAY, where negative numbers count
+/// from the end, into a day number.
+fn resolve_month_day(day: i32, days: u32) -> Option<u32> {
+    let day = if day < 0 {
+        i64::from(days) + 1 + i64::from(day)
+    } else {
+        i64::from(day)
+    };
+    u32::try_from(day)
+        .ok()
+        .filter(|day| (1..=days).contains(day))
+}
+
+/// The days of the month that are `weekday`.  With an ordinal, this is only
+/// the `ordinal`th one, counting from the end if it is negative.
+fn weekdays_in_month(
+    year: i32,
+    month: u32,
+    weekday: Weekday,
+    ordinal: Option<i32>,
+) -> Vec<u32> {
+    let days: Vec<u32> = (1..=days_in_month(year, month))
+        .filter(|day| {
+            NaiveDate::from_ymd_opt(year, month, *day)
+                .map_or(false, |date| date.weekday() == weekday)
+        })
+        .collect();
+    match ordinal {
+        None => days,
+        Some(ordinal) => {
+            let mut days = days;
+            if ordinal < 0 {
+                days.reverse();
+            }
+            usize::try_from(ordinal.unsigned_abs())
+                .ok()
+                .and_then(|nth| nth.checked_sub(1))
+                .and_then(|index| days.get(index))
+                .into_iter()
+                .copied()
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn expand(rule: &str, dtstart: &str, end: &str) -> Vec<NaiveDateTime> {
+        RRule::parse(rule).unwrap().occurrences(
+            time(dtstart),
+            time(end),
+            |local| Utc.from_utc_datetime(&local),
+        )
+    }
+
+    fn times(texts: &[&str]) -> Vec<NaiveDateTime> {
+        texts.iter().map(|text| time(text)).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            RRule::parse("FREQ=MONTHLY;BYDAY=-1FR,2MO;COUNT=3;WKST=SU"),
+            Ok(RRule {
+                freq: Frequency::Monthly,
+                interval: 1,
+                count: Some(3),
+                until: None,
+                by_day: vec![(Some(-1), Weekday::Fri), (Some(2), Weekday::Mon)],
+                by_month_day: vec![],
+                by_month: vec![],
+            })
+        );
+        assert!(RRule::parse("INTERVAL=2").is_err());
+        assert!(RRule::parse("FREQ=HOURLY").is_err());
+        assert!(RRule::parse("FREQ=DAILY;INTERVAL=0").is_err());
+        assert!(RRule::parse("FREQ=MONTHLY;BYSETPOS=-1").is_err());
+        assert!(RRule::parse("FREQ=YEARLY;BYDAY=20MO").is_err());
+    }
+
+    #[test]
+    fn test_daily() {
+        assert_eq!(
+            expand(
+                "FREQ=DAILY;COUNT=3",
+                "2020-01-30 09:00",
+                "2021-01-01 00:00"
+            ),
+            times(&[
+                "2020-01-30 09:00",
+                "2020-01-31 09:00",
+                "2020-02-01 09:00"
+            ])
+        );
+        assert_eq!(
+            expand(
+                "FREQ=DAILY;INTERVAL=2;UNTIL=20200204T090000Z",
+                "2020-01-30 09:00",
+                "2021-01-01 00:00"
+            ),
+            times(&[
+                "2020-01-30 09:00",
+                "2020-02-01 09:00",
+                "2020-02-03 09:00"
+            ])
+        );
+        // Occurrences after the end aren't needed.
+        assert_eq!(
+            expand("FREQ=DAILY", "2020-01-30 09:00", "2020-01-31 08:00"),
+            times(&["2020-01-30 09:00"])
+        );
+    }
+
+    #[test]
+    fn test_weekly() {
+        // 2020-01-01 is a Wednesday.
+        assert_eq!(
+            expand(
+                "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,WE;UNTIL=20200120",
+                "2020-01-01 14:30",
+                "2021-01-01 00:00"
+            ),
+            times(&[
+                "2020-01-01 14:30",
+                "2020-01-13 14:30",
+                "2020-01-15 14:30"
+            ])
+        );
+        assert_eq!(
+            expand(
+                "FREQ=WEEKLY;COUNT=2",
+                "2020-01-01 14:30",
+                "2021-01-01 00:00"
+            ),
+            times(&["2020-01-01 14:30", "2020-01-08 14:30"])
+        );
+    }
+
+    #[test]
+    fn test_monthly() {
+        assert_eq!(
+            expand(
+                "FREQ=MONTHLY;BYDAY=-1FR;COUNT=3",
+                "2020-01-31 10:00",
+                "2021-01-01 00:00"
+            ),
+            times(&[
+                "2020-01-31 10:00",
+                "2020-02-28 10:00",
+                "2020-03-27 10:00"
+            ])
+        );
+        // Months without a 31st are skipped.
+        assert_eq!(
+            expand(
+                "FREQ=MONTHLY;COUNT=3",
+                "2020-01-31 10:00",
+                "2021-01-01 00:00"
+            ),
+            times(&[
+                "2020-01-31 10:00",
+                "2020-03-31 10:00",
+                "2020-05-31 10:00"
+            ])
+        );
+        assert_eq!(
+            expand(
+                "FREQ=MONTHLY;BYMONTHDAY=1,-1;COUNT=4",
+                "2020-02-01 10:00",
+                "2021-01-01 00:00"
+            ),
+            times(&[
+                "2020-02-01 10:00",
+                "2020-02-29 10:00",
+                "2020-03-01 10:00",
+                "2020-03-31 10:00"
+            ])
+        );
+    }
+
+    #[test]
+    fn test_yearly() {
+        // The start of daylight saving time in Europe.
+        assert_eq!(
+            expand(
+                "FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU",
+                "1996-03-31 02:00",
+                "2020-12-31 00:00"
+            )
+            .last(),
+            Some(&time("2020-03-29 02:00"))
+        );
+        assert_eq!(
+            expand(
+                "FREQ=YEARLY;COUNT=2",
+                "2020-02-29 10:00",
+                "2030-01-01 00:00"
+            ),
+            times(&["2020-02-29 10:00", "2024-02-29 10:00"])
+        );
+    }
+}
@@ -0,0 +1,83 @@
+// This module defines how plugins compare text from the system, like window titles or process
+// names, against the values given in the config file.
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// How some text, like a window property or a process name, should be
+/// compared against a value given in the config file.
+///
+/// In the config file, this is written as an inline table with a single key,
+/// like `{ exact = "Skype" }` or `{ regex = "^Meet - .*$" }`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextMatch {
+    Exact(String),
+    Prefix(String),
+    Contains(String),
+    Regex(#[serde(deserialize_with = "deserialize_regex")] String),
+}
+
+/// Make sure a regex compiles while the config file is being parsed, so that a
+/// bad regex is reported along with where it is in the config file.
+fn deserialize_regex<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let regex = String::deserialize(deserializer)?;
+    regex::Regex::new(&regex).map_err(serde::de::Error::custom)?;
+    Ok(regex)
+}
+
+/// A `TextMatch` that is ready to be used.  Regexes have already been compiled.
+pub enum Matcher {
+    Exact(String),
+    Prefix(String),
+    Contains(String),
+    Regex(regex::Regex),
+}
+
+impl Matcher {
+    pub fn new(text_match: &TextMatch) -> Result<Self, regex::Error> {
+        Ok(match text_match {
+            TextMatch::Exact(s) => Self::Exact(s.clone()),
+            TextMatch::Prefix(s) => Self::Prefix(s.clone()),
+            TextMatch::Contains(s) => Self::Contains(s.clone()),
+            TextMatch::Regex(s) => Self::Regex(regex::Regex::new(s)?),
+        })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        match self {
+            Self::Exact(s) => text == s,
+            Self::Prefix(s) => text.starts_with(s.as_str()),
+            Self::Contains(s) => text.contains(s.as_str()),
+            Self::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_match() {
+        let matcher = |text_match| Matcher::new(&text_match).unwrap();
+
+        let exact = matcher(TextMatch::Exact(String::from("zoom")));
+        assert!(exact.is_match("zoom"));
+        assert!(!exact.is_match("zoom.real"));
+
+        let prefix = matcher(TextMatch::Prefix(String::from("Meet")));
+        assert!(prefix.is_match("Meet - abc-defg-hij"));
+        assert!(!prefix.is_match("Google Meet"));
+
+        let contains = matcher(TextMatch::Contains(String::from("Jitsi")));
+        assert!(contains.is_match("Call | Jitsi Meet"));
+        assert!(!contains.is_match("jitsi"));
+
+        let regex = matcher(TextMatch::Regex(String::from("^Meet - .*$")));
+        assert!(regex.is_match("Meet - abc-defg-hij"));
+        assert!(!regex.is_match("Meeting"));
+    }
+}
@@ -0,0 +1,484 @@
+// This module defines a plugin that stops breaks from happening while certain processes are
+// running, like a video call app or a screen recorder.
+//
+// Every time the scheduler wants to know whether it can break, all the processes in /proc are
+// checked against the rules from the config file.  For each process, this reads:
+//
+// - /proc/PID/comm: the name of the program, truncated to 15 bytes by the kernel
+// - /proc/PID/cmdline: the arguments the program was started with, separated by NUL bytes
+// - /proc/PID/status: the `Uid:` line has the user that owns the process
+//
+// Processes can exit while they are being checked, so processes that can't be read are skipped.
+//
+// User names in rules are turned into user IDs with getpwnam_r(3), so that users from LDAP or
+// systemd-homed are found as well as the ones in /etc/passwd.
+//
+// - https://man7.org/linux/man-pages/man5/proc.5.html
+// - https://man7.org/linux/man-pages/man3/getpwnam_r.3.html
+
+#![allow(unsafe_code)]
+
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::matcher::{Matcher, TextMatch};
+use super::{CanBreak, Plugin, PolicySettings};
+use crate::config::Config;
+
+const PROC_ROOT: &str = "/proc";
+
+/// The size of the buffer `getpwnam_r` starts with.  This is doubled until
+/// the entry for the user fits.
+const PASSWD_BUF_SIZE: usize = 1024;
+
+/// A single rule for a process that should prevent a break from happening.
+///
+/// A process matches a rule when it matches all of the conditions given in
+/// the rule.  Conditions that are not given are ignored.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleSettings {
+    /// A human-readable name for this rule.  This is used for logging.
+    pub name: String,
+    /// Match on the name of the program from /proc/PID/comm, like `zoom`.
+    pub comm: Option<TextMatch>,
+    /// Match on the whole command line, with the arguments separated by
+    /// spaces, like `ffmpeg -f x11grab -i :0`.
+    pub cmdline: Option<TextMatch>,
+    /// Each of these has to match at least one of the arguments, including
+    /// the program itself.
+    #[serde(default)]
+    pub args: Vec<TextMatch>,
+    /// Only match processes owned by this user.  This can be a user name or a
+    /// numeric user ID.
+    pub user: Option<String>,
+}
+
+/// Settings for the `processes` plugin.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    #[serde(default)]
+    pub rules: Vec<RuleSettings>,
+    #[serde(default)]
+    pub policy: PolicySettings,
+}
+
+impl Settings {
+    /// Check that all the rules can be used.
+    pub fn validate(&self) -> Result<(), String> {
+        Rules::new(self).map(|_rules| ())
+    }
+}
+
+/// Find the user ID of `user` in the user database.  `user` can also be a
+/// user ID already.
+fn find_uid(user: &str) -> Result<u32, String> {
+    if let Ok(uid) = user.parse() {
+        return Ok(uid);
+    }
+    let unknown_user = || format!("unknown user \"{}\"", user);
+    let c_user = CString::new(user).map_err(|_| unknown_user())?;
+    let mut buf: Vec<libc::c_char> = vec![0; PASSWD_BUF_SIZE];
+    loop {
+        let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result: *mut libc::passwd = std::ptr::null_mut();
+        let res = unsafe {
+            libc::getpwnam_r(
+                c_user.as_ptr(),
+                &mut passwd,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            )
+        };
+        if res == libc::ERANGE {
+            buf.resize(buf.len() * 2, 0);
+        } else if res != 0 {
+            return Err(format!(
+                "could not look up user \"{}\": {}",
+                user,
+                std::io::Error::from_raw_os_error(res)
+            ));
+        } else if result.is_null() {
+            return Err(unknown_user());
+        } else {
+            return Ok(passwd.pw_uid);
+        }
+    }
+}
+
+struct Rule {
+    name: String,
+    comm: Option<Matcher>,
+    cmdline: Option<Matcher>,
+    args: Vec<Matcher>,
+    uid: Option<u32>,
+}
+
+impl Rule {
+    fn new(rule_settings: &RuleSettings) -> Result<Self, String> {
+        let compile_err = |err: regex::Error| {
+            format!(
+                "could not compile regex in process rule \"{}\": {}",
+                rule_settings.name, err
+            )
+        };
+        let compile = |opt_text_match: &Option<TextMatch>| {
+            opt_text_match
+                .as_ref()
+                .map(Matcher::new)
+                .transpose()
+                .map_err(compile_err)
+        };
+
+        let rule = Self {
+            name: rule_settings.name.clone(),
+            comm: compile(&rule_settings.comm)?,
+            cmdline: compile(&rule_settings.cmdline)?,
+            args: rule_settings
+                .args
+                .iter()
+                .map(Matcher::new)
+                .collect::<Result<Vec<Matcher>, regex::Error>>()
+                .map_err(compile_err)?,
+            uid: rule_settings
+                .user
+                .as_ref()
+                .map(|user| find_uid(user))
+                .transpose()
+                .map_err(|err| {
+                    format!("process rule \"{}\": {}", rule_settings.name, err)
+                })?,
+        };
+
+        // A rule that only checks the user (or nothing at all) would match
+        // processes that are always running, so we would never be able to
+        // break.
+        if rule.comm.is_none() && rule.cmdline.is_none() && rule.args.is_empty()
+        {
+            return Err(format!(
+                "process rule \"{}\" needs at least one of comm, cmdline, or args",
+                rule.name
+            ));
+        }
+
+        Ok(rule)
+    }
+
+    fn is_match(&self, process: &Process) -> bool {
+        self.comm
+            .as_ref()
+            .map_or(true, |comm| comm.is_match(&process.comm))
+            && self
+                .cmdline
+                .as_ref()
+                .map_or(true, |cmdline| cmdline.is_match(&process.cmdline()))
+            && self.args.iter().all(|arg_matcher| {
+                process.args.iter().any(|arg| arg_matcher.is_match(arg))
+            })
+            && self.uid.map_or(true, |uid| process.uid == Some(uid))
+    }
+}
+
+struct Rules(Vec<Rule>);
+
+impl Rules {
+    fn new(settings: &Settings) -> Result<Self, String> {
+        let rules = settings
+            .rules
+            .iter()
+            .map(Rule::new)
+            .collect::<Result<Vec<Rule>, String>>()?;
+        Ok(Self(rules))
+    }
+
+    fn find_match(&self, process: &Process) -> Option<&Rule> {
+        self.0.iter().find(|rule| rule.is_match(process))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Process {
+    pid: u32,
+    comm: String,
+    /// This is empty for kernel threads.
+    args: Vec<String>,
+    /// The real user ID of the owner.
+    uid: Option<u32>,
+}
+
+impl Process {
+    /// Read the information about a process from its directory in /proc.
+    fn read(pid: u32, dir: &Path) -> std::io::Result<Self> {
+        let comm = std::fs::read_to_string(dir.join("comm"))?;
+        let cmdline = std::fs::read(dir.join("cmdline"))?;
+        let args = cmdline
+            .split(|b| *b == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| String::from_utf8_lossy(arg).into_owned())
+            .collect();
+        let uid = std::fs::read_to_string(dir.join("status"))?
+            .lines()
+            .find_map(|line| line.strip_prefix("Uid:"))
+            .and_then(|uids| uids.split_whitespace().next())
+            .and_then(|uid| uid.parse().ok());
+        Ok(Self {
+            pid,
+            comm: comm.trim_end_matches('\n').to_string(),
+            args,
+            uid,
+        })
+    }
+
+    fn cmdline(&self) -> String {
+        self.args.join(" ")
+    }
+}
+
+/// Read all the processes under `proc_root`.
+fn all_processes(proc_root: &Path) -> std::io::Result<Vec<Process>> {
+    let mut processes = vec![];
+    for entry in std::fs::read_dir(proc_root)? {
+        let entry = entry?;
+        let pid = match entry.file_name().to_str().and_then(|s| s.parse().ok())
+        {
+            None => continue,
+            Some(pid) => pid,
+        };
+        if let Ok(process) = Process::read(pid, &entry.path()) {
+            processes.push(process);
+        }
+    }
+    Ok(processes)
+}
+
+pub struct Processes {
+    proc_root: PathBuf,
+    rules: Rules,
+}
+
+impl Processes {
+    pub fn new(config: &Config) -> Result<Self, ()> {
+        Self::with_proc_root(
+            &config.settings.all_plugin_settings.processes,
+            PathBuf::from(PROC_ROOT),
+        )
+        .map_err(|err| {
+            println!("Could not set up process rules: {}", err);
+        })
+    }
+
+    fn with_proc_root(
+        settings: &Settings,
+        proc_root: PathBuf,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            proc_root,
+            rules: Rules::new(settings)?,
+        })
+    }
+
+    fn can_break(&self) -> std::io::Result<CanBreak> {
+        if self.rules.0.is_empty() {
+            return Ok(CanBreak::Yes);
+        }
+        for process in all_processes(&self.proc_root)? {
+            if let Some(rule) = self.rules.find_match(&process) {
+                println!(
+                    "processes: process {} ({}) matched rule \"{}\"",
+                    process.pid,
+                    process.cmdline(),
+                    rule.name
+                );
+                return Ok(CanBreak::No);
+            }
+        }
+        Ok(CanBreak::Yes)
+    }
+}
+
+impl Plugin for Processes {
+    fn can_break_now(&self) -> Result<CanBreak, Box<dyn std::error::Error>> {
+        self.can_break()
+            .map_err(|err| Box::new(err) as Box<dyn std::error::Error>)
+    }
+
+    fn name(&self) -> String {
+        String::from("processes")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::scheduler::plugins::fake_proc::FakeProc;
+
+    fn add_process(
+        fake_proc: &FakeProc,
+        pid: u32,
+        comm: &str,
+        args: &[&str],
+        uid: u32,
+    ) {
+        let dir = fake_proc.add_process(pid, comm);
+        let mut cmdline = args.join("\0");
+        if !cmdline.is_empty() {
+            cmdline.push('\0');
+        }
+        std::fs::write(dir.join("cmdline"), cmdline).unwrap();
+        std::fs::write(
+            dir.join("status"),
+            format!(
+                "Name:\t{}\nUmask:\t0022\nUid:\t{}\t{}\t{}\t{}\n",
+                comm, uid, uid, uid, uid
+            ),
+        )
+        .unwrap();
+    }
+
+    fn new_plugin(
+        fake_proc: &FakeProc,
+        rules: &str,
+    ) -> Result<Processes, String> {
+        let settings: Settings = toml::from_str(rules).unwrap();
+        Processes::with_proc_root(&settings, fake_proc.root.clone())
+    }
+
+    #[test]
+    fn test_read_process() {
+        let fake_proc = FakeProc::new("processes-read");
+        add_process(&fake_proc, 1, "kthreadd", &[], 0);
+        add_process(
+            &fake_proc,
+            42,
+            "ffmpeg",
+            &["ffmpeg", "-f", "x11grab"],
+            1000,
+        );
+
+        let mut processes = all_processes(&fake_proc.root).unwrap();
+        processes.sort_by_key(|process| process.pid);
+        assert_eq!(
+            processes,
+            vec![
+                Process {
+                    pid: 1,
+                    comm: String::from("kthreadd"),
+                    args: vec![],
+                    uid: Some(0),
+                },
+                Process {
+                    pid: 42,
+                    comm: String::from("ffmpeg"),
+                    args: vec![
+                        String::from("ffmpeg"),
+                        String::from("-f"),
+                        String::from("x11grab"),
+                    ],
+                    uid: Some(1000),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rules() {
+        let fake_proc = FakeProc::new("processes-rules");
+        add_process(&fake_proc, 1, "systemd", &["/sbin/init"], 0);
+        add_process(
+            &fake_proc,
+            100,
+            "ffmpeg",
+            &["ffmpeg", "-i", "in.mp4"],
+            1000,
+        );
+        add_process(&fake_proc, 101, "zoom", &["/opt/zoom/zoom"], 1001);
+
+        let can_break = |rules: &str| {
+            new_plugin(&fake_proc, rules)
+                .unwrap()
+                .can_break()
+                .unwrap()
+                .into_bool()
+        };
+
+        assert!(can_break(""));
+        assert!(!can_break(
+            r#"
+            [[rules]]
+            name = "Zoom"
+            comm = { exact = "zoom" }
+            "#
+        ));
+
+        // ffmpeg is running, but isn't recording the screen.
+        let screen_recording = r#"
+            [[rules]]
+            name = "screen recording"
+            comm = { exact = "ffmpeg" }
+            args = [{ exact = "x11grab" }]
+            "#;
+        assert!(can_break(screen_recording));
+        add_process(
+            &fake_proc,
+            102,
+            "ffmpeg",
+            &["ffmpeg", "-f", "x11grab"],
+            1000,
+        );
+        assert!(!can_break(screen_recording));
+        assert!(!can_break(
+            r#"
+            [[rules]]
+            name = "screen recording"
+            cmdline = { contains = "ffmpeg -f x11grab" }
+            "#
+        ));
+
+        // Zoom is running, but for a different user.
+        assert!(can_break(
+            r#"
+            [[rules]]
+            name = "Zoom"
+            comm = { exact = "zoom" }
+            user = "root"
+            "#
+        ));
+        assert!(!can_break(
+            r#"
+            [[rules]]
+            name = "Zoom"
+            comm = { exact = "zoom" }
+            user = "1001"
+            "#
+        ));
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let fake_proc = FakeProc::new("processes-invalid");
+        assert!(new_plugin(
+            &fake_proc,
+            r#"
+            [[rules]]
+            name = "only a user"
+            user = "root"
+            "#
+        )
+        .is_err());
+        assert!(new_plugin(
+            &fake_proc,
+            r#"
+            [[rules]]
+            name = "unknown user"
+            comm = { exact = "zoom" }
+            user = "break-time-no-such-user"
+            "#
+        )
+        .is_err());
+    }
+}
@@ -1,27 +1,42 @@
-use super::{CanBreak, Plugin};
+use super::matcher::{Matcher, TextMatch};
+use super::{CanBreak, Plugin, PolicySettings};
 
 use crate::config::Config;
-use crate::prelude::*;
 
-use crate::x11::X11;
+use serde::{Deserialize, Serialize};
+
+use crate::x11::{WindowFinder, Windows, X11};
 
 pub struct WindowTitles {
     x11: X11,
     net_wm_name_atom: xcb::Atom,
     utf8_string_atom: xcb::Atom,
+    window_finder: WindowFinder,
+    windows: Windows,
+    rules: Rules,
 }
 
 impl WindowTitles {
-    pub fn new(_config: &Config) -> Result<Self, ()> {
+    pub fn new(config: &Config) -> Result<Self, ()> {
+        let settings =
+            &config.settings.all_plugin_settings.x11_window_title_checker;
+        let rules = Rules::new(settings).map_err(|err| {
+            println!("Could not set up window title rules: {}", err);
+        })?;
+
         let x11 = X11::connect();
 
         let net_wm_name_atom = x11.create_atom("_NET_WM_NAME").ok_or(())?;
         let utf8_string_atom = x11.create_atom("UTF8_STRING").ok_or(())?;
+        let window_finder = WindowFinder::new(&x11).ok_or(())?;
 
         Ok(Self {
             x11,
             net_wm_name_atom,
             utf8_string_atom,
+            window_finder,
+            windows: settings.windows,
+            rules,
         })
     }
 
@@ -93,245 +108,278 @@ impl WindowTitles {
     }
 
     fn get_all_win_props(&self) -> Result<Vec<WinProps>, ()> {
-        let wins = self.get_all_wins()?;
+        let wins = self.get_wins_to_check()?;
         Ok(self.get_all_win_props_from_wins(&wins))
     }
 
-    fn get_root_win(&self) -> Result<xcb::Window, ()> {
-        let setup: xcb::Setup = self.x11.conn.get_setup();
-        let mut roots: xcb::ScreenIterator = setup.roots();
-        let preferred_screen_pos = usize::try_from(self.x11.preferred_screen)
-            .expect("x11 preferred_screen is not positive");
-        let screen: xcb::Screen = roots.nth(preferred_screen_pos).ok_or(())?;
-        Ok(screen.root())
-    }
-
-    fn get_all_wins(&self) -> Result<Vec<xcb::Window>, ()> {
-        let root_win = self.get_root_win()?;
-
-        let query_tree_reply: xcb::QueryTreeReply =
-            xcb::xproto::query_tree(&self.x11.conn, root_win)
-                .get_reply()
-                .map_err(|_| ())?;
-
-        Ok(query_tree_reply.children().to_vec())
+    fn get_wins_to_check(&self) -> Result<Vec<xcb::Window>, ()> {
+        self.window_finder
+            .get_wins(&self.x11, self.windows)
+            .ok_or(())
     }
 
     fn can_break(&self) -> Result<CanBreak, ()> {
         let all_win_props: Vec<WinProps> = self.get_all_win_props()?;
-        let all_can_break_preds = CanBreakPreds::all();
-        let can_break_bool = all_win_props.iter().all(|win_props| {
-            all_can_break_preds.can_break(win_props).into_bool()
-        });
+        let can_break_bool = all_win_props
+            .iter()
+            .all(|win_props| self.rules.can_break(win_props).into_bool());
         let can_break_res = CanBreak::from_bool(can_break_bool);
         Ok(can_break_res)
     }
 }
 
-struct CanBreakPred<F>(F);
-
-impl CanBreakPred<Box<dyn Fn(&WinProps) -> CanBreak>> {
-    fn from_name_class<G>(g: G) -> Self
-    where
-        G: 'static + Fn(&str, &str, &str) -> CanBreak,
-    {
-        Self(Box::new(move |win_props: &WinProps| {
-            match (
-                &win_props.net_wm_name,
-                &win_props.class_name,
-                &win_props.class,
-            ) {
-                (Ok(net_wm_name), Ok(class_name), Ok(class)) => {
-                    g(&net_wm_name, &class_name, &class)
-                }
-                _ => CanBreak::Yes,
-            }
-        }))
+/// A single rule for a window that should prevent a break from happening.
+///
+/// A window matches a rule when it matches all of the conditions given in the
+/// rule.  Conditions that are not given are ignored.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleSettings {
+    /// A human-readable name for this rule.  This is only used for logging.
+    pub name: String,
+    /// Only match windows from one of the web browsers break-time knows about.
+    #[serde(default)]
+    pub browser: bool,
+    /// Match on the class part of `WM_CLASS`, like `Firefox`.
+    pub class: Option<TextMatch>,
+    /// Match on the instance name part of `WM_CLASS`, like `Navigator`.
+    pub class_name: Option<TextMatch>,
+    /// Match on the `_NET_WM_NAME` window title.
+    pub net_wm_name: Option<TextMatch>,
+    /// Match on the `WM_NAME` window title.
+    pub wm_name: Option<TextMatch>,
+}
+
+impl RuleSettings {
+    fn browser_title(name: &str, title: TextMatch) -> Self {
+        Self {
+            name: String::from(name),
+            browser: true,
+            class: None,
+            class_name: None,
+            net_wm_name: Some(title),
+            wm_name: None,
+        }
     }
 
-    fn can_break(&self, win_props: &WinProps) -> CanBreak {
-        self.0(win_props)
+    fn app(
+        name: &str,
+        class: TextMatch,
+        class_name: TextMatch,
+        net_wm_name: TextMatch,
+    ) -> Self {
+        Self {
+            name: String::from(name),
+            browser: false,
+            class: Some(class),
+            class_name: Some(class_name),
+            net_wm_name: Some(net_wm_name),
+            wm_name: None,
+        }
     }
 }
 
-struct CanBreakPreds<F>(Vec<CanBreakPred<F>>);
-
-impl CanBreakPreds<Box<dyn Fn(&WinProps) -> CanBreak>> {
-    fn all() -> Self {
-        Self(vec![
-            // BigBlueButton in browser
-            CanBreakPred::from_name_class(
-                |net_wm_name: &str,
-                 class_name: &str,
-                 class: &str|
-                 -> CanBreak {
-                    browser_title_starts_with(
-                        class,
-                        class_name,
-                        net_wm_name,
-                        "BigBlueButton",
-                    )
-                },
-            ),
-            // Google Meet in browser
-            CanBreakPred::from_name_class(
-                |net_wm_name: &str,
-                 class_name: &str,
-                 class: &str|
-                 -> CanBreak {
-                    browser_title_starts_with(
-                        class,
-                        class_name,
-                        net_wm_name,
-                        "Meet",
-                    )
-                },
-            ),
-            // Jitsi in browser
-            CanBreakPred::from_name_class(
-                |net_wm_name: &str,
-                 class_name: &str,
-                 class: &str|
-                 -> CanBreak {
-                    browser_title_contains(
-                        class,
-                        class_name,
-                        net_wm_name,
-                        "Jitsi Meet",
-                    )
-                },
-            ),
-            // Slack: Initiating a Slack call in browser
-            CanBreakPred::from_name_class(
-                |net_wm_name: &str,
-                 class_name: &str,
-                 class: &str|
-                 -> CanBreak {
-                    browser_title_starts_with(
-                        class,
-                        class_name,
-                        net_wm_name,
-                        "Slack | Calling ",
-                    )
-                },
-            ),
-            // Slack: In a Slack call in browser
-            CanBreakPred::from_name_class(
-                |net_wm_name: &str,
-                 class_name: &str,
-                 class: &str|
-                 -> CanBreak {
-                    browser_title_starts_with(
-                        class,
-                        class_name,
-                        net_wm_name,
-                        "Slack | Slack call ",
-                    )
-                },
-            ),
-            // Skype
-            CanBreakPred::from_name_class(
-                |net_wm_name: &str,
-                 class_name: &str,
-                 class: &str|
-                 -> CanBreak {
-                    if class == "Skype"
-                        && class_name == "skype"
-                        && net_wm_name == "Skype"
-                    {
-                        CanBreak::No
-                    } else {
-                        CanBreak::Yes
-                    }
-                },
-            ),
-            // Zoom
-            CanBreakPred::from_name_class(
-                |net_wm_name: &str,
-                 class_name: &str,
-                 class: &str|
-                 -> CanBreak {
-                    if class.contains("zoom")
-                        && class_name.contains("zoom")
-                        && net_wm_name.contains("Zoom")
-                    {
-                        CanBreak::No
-                    } else {
-                        CanBreak::Yes
-                    }
-                },
-            ),
-        ])
+/// Settings for the `x11_window_title_checker` plugin.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    /// Whether or not to use the rules from `default_rules()`.
+    #[serde(default = "default_use_default_rules")]
+    pub use_default_rules: bool,
+    #[serde(default = "default_windows")]
+    pub windows: Windows,
+    /// Additional rules from the user.
+    #[serde(default)]
+    pub rules: Vec<RuleSettings>,
+    #[serde(default)]
+    pub policy: PolicySettings,
+}
+
+const fn default_use_default_rules() -> bool {
+    true
+}
+
+const fn default_windows() -> Windows {
+    Windows::TopLevel
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            use_default_rules: default_use_default_rules(),
+            windows: default_windows(),
+            rules: vec![],
+            policy: PolicySettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Check that all the rules can be used.
+    pub fn validate(&self) -> Result<(), String> {
+        Rules::new(self).map(|_rules| ())
     }
 
-    fn can_break(&self, win_props: &WinProps) -> CanBreak {
-        CanBreak::from_bool(self.0.iter().all(|can_break_pred| {
-            can_break_pred.can_break(win_props).into_bool()
-        }))
+    fn all_rules(&self) -> Vec<RuleSettings> {
+        let mut all_rules = if self.use_default_rules {
+            default_rules()
+        } else {
+            vec![]
+        };
+        all_rules.extend(self.rules.iter().cloned());
+        all_rules
     }
 }
 
-fn is_browser(class: &str, class_name: &str) -> bool {
-    (class == "Chromium-browser" && class_name == "chromium-browser")
-        || (class == "Chromium" && class_name == "chromium")
-        || (class == "Firefox" && class_name == "Navigator")
-        || (class == "firefox" && class_name == "Navigator")
-        || (class == "firefox_firefox" && class_name == "Navigator")
-        || (class == "Firefox" && class_name == "firefox")
+/// The built-in rules.  These can be turned off with `use_default_rules = false`.
+fn default_rules() -> Vec<RuleSettings> {
+    vec![
+        RuleSettings::browser_title(
+            "BigBlueButton in browser",
+            TextMatch::Prefix(String::from("BigBlueButton")),
+        ),
+        RuleSettings::browser_title(
+            "Google Meet in browser",
+            TextMatch::Prefix(String::from("Meet")),
+        ),
+        RuleSettings::browser_title(
+            "Jitsi in browser",
+            TextMatch::Contains(String::from("Jitsi Meet")),
+        ),
+        RuleSettings::browser_title(
+            "Slack: Initiating a Slack call in browser",
+            TextMatch::Prefix(String::from("Slack | Calling ")),
+        ),
+        RuleSettings::browser_title(
+            "Slack: In a Slack call in browser",
+            TextMatch::Prefix(String::from("Slack | Slack call ")),
+        ),
+        RuleSettings::app(
+            "Skype",
+            TextMatch::Exact(String::from("Skype")),
+            TextMatch::Exact(String::from("skype")),
+            TextMatch::Exact(String::from("Skype")),
+        ),
+        RuleSettings::app(
+            "Zoom",
+            TextMatch::Contains(String::from("zoom")),
+            TextMatch::Contains(String::from("zoom")),
+            TextMatch::Contains(String::from("Zoom")),
+        ),
+    ]
 }
 
-fn browser_title_starts_with_raw(
-    class: &str,
-    class_name: &str,
-    net_wm_name: &str,
-    title_starts_with: &str,
+/// Check whether a window property matches.  If the property couldn't be
+/// read from the window, then it never matches.
+fn is_prop_match(
+    opt_matcher: Option<&Matcher>,
+    prop: &Result<String, ()>,
 ) -> bool {
-    is_browser(class, class_name) && net_wm_name.starts_with(title_starts_with)
+    match (opt_matcher, prop) {
+        (None, _) => true,
+        (Some(matcher), Ok(prop)) => matcher.is_match(prop),
+        (Some(_), Err(())) => false,
+    }
 }
 
-fn browser_title_starts_with(
-    class: &str,
-    class_name: &str,
-    net_wm_name: &str,
-    title_starts_with: &str,
-) -> CanBreak {
-    if browser_title_starts_with_raw(
-        class,
-        class_name,
-        net_wm_name,
-        title_starts_with,
-    ) {
-        CanBreak::No
-    } else {
-        CanBreak::Yes
-    }
+struct Rule {
+    name: String,
+    browser: bool,
+    class: Option<Matcher>,
+    class_name: Option<Matcher>,
+    net_wm_name: Option<Matcher>,
+    wm_name: Option<Matcher>,
 }
 
-fn browser_title_contains_raw(
-    class: &str,
-    class_name: &str,
-    net_wm_name: &str,
-    title_contains: &str,
-) -> bool {
-    is_browser(class, class_name) && net_wm_name.contains(title_contains)
+impl Rule {
+    fn new(rule_settings: &RuleSettings) -> Result<Self, String> {
+        let compile = |opt_text_match: &Option<TextMatch>| {
+            opt_text_match
+                .as_ref()
+                .map(Matcher::new)
+                .transpose()
+                .map_err(|err| {
+                    format!(
+                        "could not compile regex in window title rule \"{}\": {}",
+                        rule_settings.name, err
+                    )
+                })
+        };
+
+        let rule = Self {
+            name: rule_settings.name.clone(),
+            browser: rule_settings.browser,
+            class: compile(&rule_settings.class)?,
+            class_name: compile(&rule_settings.class_name)?,
+            net_wm_name: compile(&rule_settings.net_wm_name)?,
+            wm_name: compile(&rule_settings.wm_name)?,
+        };
+
+        // A rule without any conditions would match every single window, which means we would
+        // never be able to break.
+        if !rule.browser
+            && rule.class.is_none()
+            && rule.class_name.is_none()
+            && rule.net_wm_name.is_none()
+            && rule.wm_name.is_none()
+        {
+            return Err(format!(
+                "window title rule \"{}\" doesn't have any conditions, so it would match every window",
+                rule.name
+            ));
+        }
+
+        Ok(rule)
+    }
+
+    fn is_match(&self, win_props: &WinProps) -> bool {
+        let is_browser_match = !self.browser
+            || match (&win_props.class, &win_props.class_name) {
+                (Ok(class), Ok(class_name)) => is_browser(class, class_name),
+                _ => false,
+            };
+
+        is_browser_match
+            && is_prop_match(self.class.as_ref(), &win_props.class)
+            && is_prop_match(self.class_name.as_ref(), &win_props.class_name)
+            && is_prop_match(self.net_wm_name.as_ref(), &win_props.net_wm_name)
+            && is_prop_match(self.wm_name.as_ref(), &win_props.wm_name)
+    }
 }
 
-fn browser_title_contains(
-    class: &str,
-    class_name: &str,
-    net_wm_name: &str,
-    title_contains: &str,
-) -> CanBreak {
-    if browser_title_contains_raw(
-        class,
-        class_name,
-        net_wm_name,
-        title_contains,
-    ) {
-        CanBreak::No
-    } else {
-        CanBreak::Yes
+struct Rules(Vec<Rule>);
+
+impl Rules {
+    fn new(settings: &Settings) -> Result<Self, String> {
+        let rules = settings
+            .all_rules()
+            .iter()
+            .map(Rule::new)
+            .collect::<Result<Vec<Rule>, String>>()?;
+        Ok(Self(rules))
     }
+
+    fn can_break(&self, win_props: &WinProps) -> CanBreak {
+        match self.0.iter().find(|rule| rule.is_match(win_props)) {
+            None => CanBreak::Yes,
+            Some(rule) => {
+                println!(
+                    "window_titles: window {:?} matched rule \"{}\"",
+                    win_props.net_wm_name, rule.name
+                );
+                CanBreak::No
+            }
+        }
+    }
+}
+
+fn is_browser(class: &str, class_name: &str) -> bool {
+    (class == "Chromium-browser" && class_name == "chromium-browser")
+        || (class == "Chromium" && class_name == "chromium")
+        || (class == "Firefox" && class_name == "Navigator")
+        || (class == "firefox" && class_name == "Navigator")
+        || (class == "firefox_firefox" && class_name == "Navigator")
+        || (class == "Firefox" && class_name == "firefox")
 }
 
 const PROP_STARTING_OFFSET: u32 = 0;
@@ -346,7 +394,6 @@ struct WinPropCookies<'a> {
 
 #[derive(Clone, Debug)]
 struct WinProps {
-    #[allow(dead_code)]
     wm_name: Result<String, ()>,
     net_wm_name: Result<String, ()>,
     #[allow(dead_code)]
@@ -476,3 +523,106 @@ impl Plugin for WindowTitles {
         String::from("window_titles")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn win_props(class: &str, class_name: &str, net_wm_name: &str) -> WinProps {
+        WinProps {
+            wm_name: Err(()),
+            net_wm_name: Ok(String::from(net_wm_name)),
+            transient_for_wins: Ok(vec![]),
+            class_name: Ok(String::from(class_name)),
+            class: Ok(String::from(class)),
+        }
+    }
+
+    #[test]
+    fn test_default_rules() {
+        let rules = Rules::new(&Settings::default()).unwrap();
+
+        let meet_in_firefox =
+            win_props("Firefox", "Navigator", "Meet - abc-defg-hij");
+        let meet_in_terminal =
+            win_props("Alacritty", "alacritty", "Meet - abc-defg-hij");
+        let zoom = win_props("zoom", "zoom", "Zoom Meeting");
+
+        assert!(!rules.can_break(&meet_in_firefox).into_bool());
+        assert!(rules.can_break(&meet_in_terminal).into_bool());
+        assert!(!rules.can_break(&zoom).into_bool());
+    }
+
+    #[test]
+    fn test_rules_from_config() {
+        let settings: Settings = toml::from_str(
+            r#"
+            use_default_rules = false
+            windows = "visible"
+
+            [[rules]]
+            name = "Microsoft Teams"
+            class = { exact = "Microsoft Teams - Preview" }
+            net_wm_name = { regex = "^Meeting .* \\| Microsoft Teams$" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(settings.windows, Windows::Visible);
+        let rules = Rules::new(&settings).unwrap();
+
+        let teams_meeting = win_props(
+            "Microsoft Teams - Preview",
+            "microsoft teams - preview",
+            "Meeting with Bob | Microsoft Teams",
+        );
+        let teams_chat = win_props(
+            "Microsoft Teams - Preview",
+            "microsoft teams - preview",
+            "Chat | Microsoft Teams",
+        );
+        let meet_in_firefox =
+            win_props("Firefox", "Navigator", "Meet - abc-defg-hij");
+
+        assert!(!rules.can_break(&teams_meeting).into_bool());
+        assert!(rules.can_break(&teams_chat).into_bool());
+        assert!(rules.can_break(&meet_in_firefox).into_bool());
+    }
+
+    #[test]
+    fn test_invalid_settings() {
+        let err = toml::from_str::<Settings>(
+            r#"
+            use_default_rules = false
+
+            [[rules]]
+            name = "typo"
+            nte_wm_name = { exact = "Zoom" }
+            "#,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("unknown field `nte_wm_name`"));
+        assert!(err.contains("at line 4"));
+
+        let err = toml::from_str::<Settings>(
+            r#"
+            [[rules]]
+            name = "bad regex"
+            net_wm_name = { regex = "(unclosed" }
+            "#,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("unclosed group"));
+        assert!(err.contains("for key `rules.net_wm_name` at line 2"));
+
+        let settings: Settings = toml::from_str(
+            r#"
+            [[rules]]
+            name = "no conditions"
+            "#,
+        )
+        .unwrap();
+        assert!(settings.validate().is_err());
+    }
+}
@@ -0,0 +1,63 @@
+// This module saves the state of the break countdowns to a file in the cache directory, so that
+// restarting break-time (or logging out) doesn't give you a fresh countdown.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const STATE_FILE_NAME: &str = "scheduler-state.json";
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SavedState {
+    /// Unix time (in seconds) when this state was saved.
+    pub saved_at: u64,
+    /// Unix time (in seconds) when the last break ended.
+    pub last_break_end: Option<u64>,
+    /// The number of seconds remaining until the next break, for each break
+    /// tier name.
+    pub remaining_seconds: HashMap<String, u64>,
+}
+
+impl SavedState {
+    fn path(cache_dir: &Path) -> PathBuf {
+        cache_dir.join(STATE_FILE_NAME)
+    }
+
+    /// Load the saved state.  This returns `None` if there is no saved state,
+    /// or it can't be read.
+    pub fn load(cache_dir: &Path) -> Option<Self> {
+        let state_file = std::fs::read_to_string(Self::path(cache_dir)).ok()?;
+        match serde_json::from_str(&state_file) {
+            Err(err) => {
+                println!("Could not parse the saved scheduler state, so ignoring it: {}", err);
+                None
+            }
+            Ok(saved_state) => Some(saved_state),
+        }
+    }
+
+    pub fn save(&self, cache_dir: &Path) -> std::io::Result<()> {
+        let state_file = serde_json::to_string(self)?;
+
+        // Write to a temporary file first and then rename it, so that we never leave a
+        // half-written state file around if we get killed.
+        let path = Self::path(cache_dir);
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, state_file)?;
+        std::fs::rename(&tmp_path, &path)
+    }
+
+    /// The amount of time since this state was saved.  This is approximately the amount of time
+    /// break-time wasn't running, like when the computer was turned off.
+    pub fn time_since_saved(&self, now: u64) -> Duration {
+        Duration::from_secs(now.saturating_sub(self.saved_at))
+    }
+}
+
+/// The current Unix time in seconds.
+pub fn unix_time_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
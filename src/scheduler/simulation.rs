@@ -0,0 +1,147 @@
+// This module defines a simulated clock, event source, and outbox for testing the scheduler.
+//
+// Time only passes when the scheduler waits for an event.  When it waits with a timeout, the
+// simulated clock jumps straight to the next event, or to the end of the timeout if there is no
+// event before then.
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+use super::events::{Clock, EventSource, Outbox};
+use super::{InnerMsg, Msg};
+
+#[derive(Clone, Debug)]
+pub struct SimulatedClock {
+    start: Instant,
+    /// The amount of simulated time since the start of the simulation.
+    elapsed: Rc<Cell<Duration>>,
+}
+
+impl SimulatedClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Rc::new(Cell::new(Duration::from_secs(0))),
+        }
+    }
+
+    /// The amount of simulated time since the start of the simulation.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed.get()
+    }
+
+    fn advance_to(&self, time: Duration) {
+        if time > self.elapsed.get() {
+            self.elapsed.set(time);
+        }
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed.get()
+    }
+}
+
+pub struct SimulatedEvents {
+    clock: SimulatedClock,
+    /// Messages for `recv_timeout`, with the time they are sent at.
+    msgs: VecDeque<(Duration, InnerMsg)>,
+    /// Messages for `recv_break_ending`, with how long after the break or
+    /// pause starts they are sent.
+    break_endings: VecDeque<(Duration, Msg)>,
+    /// The simulation stops once this much simulated time has passed.
+    end: Duration,
+}
+
+impl SimulatedEvents {
+    pub fn new(clock: &SimulatedClock, end: Duration) -> Self {
+        Self {
+            clock: clock.clone(),
+            msgs: VecDeque::new(),
+            break_endings: VecDeque::new(),
+            end,
+        }
+    }
+
+    /// Send `msg` at `time`.  This has to be called in the order the messages
+    /// are sent.
+    pub fn at(mut self, time: Duration, msg: InnerMsg) -> Self {
+        self.msgs.push_back((time, msg));
+        self
+    }
+
+    /// End the next break or pause with `msg`, `after` it started.
+    pub fn break_ending(mut self, after: Duration, msg: Msg) -> Self {
+        self.break_endings.push_back((after, msg));
+        self
+    }
+}
+
+impl EventSource for SimulatedEvents {
+    fn recv_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<InnerMsg, RecvTimeoutError> {
+        let now = self.clock.elapsed();
+        let deadline = now + timeout;
+        match self.msgs.front() {
+            Some((time, _)) if *time <= deadline.min(self.end) => {
+                let (time, msg) = self.msgs.pop_front().unwrap();
+                self.clock.advance_to(time);
+                Ok(msg)
+            }
+            _ if deadline > self.end => {
+                self.clock.advance_to(self.end);
+                Err(RecvTimeoutError::Disconnected)
+            }
+            _ => {
+                self.clock.advance_to(deadline);
+                Err(RecvTimeoutError::Timeout)
+            }
+        }
+    }
+
+    fn recv_break_ending(&mut self) -> Option<Msg> {
+        match self.break_endings.pop_front() {
+            Some((after, msg)) if self.clock.elapsed() + after <= self.end => {
+                self.clock.advance_to(self.clock.elapsed() + after);
+                Some(msg)
+            }
+            _ => {
+                self.clock.advance_to(self.end);
+                None
+            }
+        }
+    }
+}
+
+/// Keeps all the messages the scheduler sends, with the simulated time they
+/// were sent at.
+#[derive(Clone, Debug)]
+pub struct SimulatedOutbox {
+    clock: SimulatedClock,
+    msgs: Rc<RefCell<Vec<(Duration, crate::Msg)>>>,
+}
+
+impl SimulatedOutbox {
+    pub fn new(clock: &SimulatedClock) -> Self {
+        Self {
+            clock: clock.clone(),
+            msgs: Rc::new(RefCell::new(vec![])),
+        }
+    }
+
+    pub fn msgs(&self) -> Vec<(Duration, crate::Msg)> {
+        self.msgs.borrow().clone()
+    }
+}
+
+impl Outbox for SimulatedOutbox {
+    fn send(&self, msg: crate::Msg) {
+        self.msgs.borrow_mut().push((self.clock.elapsed(), msg));
+    }
+}
@@ -0,0 +1,158 @@
+// This module defines the Status, which is what the main thread knows about the current state of
+// break-time.  It is used to answer status requests that come in on the control socket, for
+// instance from status bars.
+
+use serde::Serialize;
+use std::time::Duration;
+
+use super::config::Config;
+use super::scheduler::plugin_runner::PluginResult;
+use super::tray;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+pub enum State {
+    CountDownToBreak,
+    Paused,
+    WaitingForBreakEnd,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Status {
+    pub state: State,
+    /// The number of seconds until the next break.  This is `None` when
+    /// break-time is not counting down to a break, or the scheduler hasn't
+    /// told us yet.
+    pub seconds_until_break: Option<u64>,
+    pub idle_detection_enabled: bool,
+    /// The names of the plugins that are stopping the next break from
+    /// starting.
+    pub blocking_plugins: Vec<String>,
+    /// The number of seconds the plugins have been delaying the next break.
+    /// This is `None` when the next break isn't blocked.
+    pub seconds_deferred: Option<u64>,
+    /// What each plugin answered the last time they were asked whether a
+    /// break could happen.
+    pub plugin_results: Vec<PluginResult>,
+}
+
+impl Status {
+    pub const fn new(config: &Config) -> Self {
+        Self {
+            state: State::CountDownToBreak,
+            seconds_until_break: None,
+            idle_detection_enabled: config.settings.idle_detection_enabled,
+            blocking_plugins: vec![],
+            seconds_deferred: None,
+            plugin_results: vec![],
+        }
+    }
+
+    pub const fn is_break_in_progress(&self) -> bool {
+        matches!(self.state, State::WaitingForBreakEnd)
+    }
+
+    pub const fn is_paused(&self) -> bool {
+        matches!(self.state, State::Paused)
+    }
+
+    pub fn set_state(&mut self, state: State) {
+        self.state = state;
+        self.seconds_until_break = None;
+        if state != State::CountDownToBreak {
+            self.set_blocking_plugins(vec![], Duration::from_secs(0));
+        }
+    }
+
+    pub fn set_blocking_plugins(
+        &mut self,
+        blocking_plugins: Vec<String>,
+        deferral: Duration,
+    ) {
+        self.seconds_deferred = if blocking_plugins.is_empty() {
+            None
+        } else {
+            Some(deferral.as_secs())
+        };
+        self.blocking_plugins = blocking_plugins;
+    }
+
+    pub fn to_text(&self) -> String {
+        match self.state {
+            State::WaitingForBreakEnd => String::from("a break is in progress"),
+            State::Paused => String::from("paused"),
+            State::CountDownToBreak if !self.blocking_plugins.is_empty() => {
+                match self.seconds_deferred {
+                    Some(seconds_deferred) if seconds_deferred > 0 => format!(
+                        "break pending for {}, blocked by: {}",
+                        tray::remaining_duration_to_text(Duration::from_secs(
+                            seconds_deferred
+                        )),
+                        self.blocking_plugins.join(", ")
+                    ),
+                    _ => format!(
+                        "break pending, blocked by: {}",
+                        self.blocking_plugins.join(", ")
+                    ),
+                }
+            }
+            State::CountDownToBreak => match self.seconds_until_break {
+                None => String::from("counting down to the next break"),
+                Some(seconds_until_break) => format!(
+                    "{} until next break",
+                    tray::remaining_duration_to_text(Duration::from_secs(
+                        seconds_until_break
+                    ))
+                ),
+            },
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self)
+            .expect("Status should always be serializable to JSON")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::plugin_runner::Answer;
+
+    #[test]
+    fn test_to_json() {
+        let status = Status {
+            state: State::CountDownToBreak,
+            seconds_until_break: Some(300),
+            idle_detection_enabled: true,
+            blocking_plugins: vec![String::from("window_titles")],
+            seconds_deferred: Some(120),
+            plugin_results: vec![
+                PluginResult {
+                    name: String::from("window_titles"),
+                    answer: Answer::No,
+                    duration: Duration::from_millis(12),
+                    blocks_break: true,
+                    disabled: false,
+                    max_deferral_exceeded: false,
+                },
+                PluginResult {
+                    name: String::from("google_calendar"),
+                    answer: Answer::Error(String::from("no network")),
+                    duration: Duration::from_millis(3400),
+                    blocks_break: true,
+                    disabled: false,
+                    max_deferral_exceeded: false,
+                },
+            ],
+        };
+
+        assert_eq!(
+            status.to_json(),
+            r#"{"state":"CountDownToBreak","seconds_until_break":300,"idle_detection_enabled":true,"blocking_plugins":["window_titles"],"seconds_deferred":120,"plugin_results":[{"name":"window_titles","answer":"no","milliseconds":12,"blocks_break":true,"disabled":false,"max_deferral_exceeded":false},{"name":"google_calendar","answer":{"error":"no network"},"milliseconds":3400,"blocks_break":true,"disabled":false,"max_deferral_exceeded":false}]}"#
+        );
+        assert_eq!(
+            status.to_text(),
+            "break pending for 2 minutes, blocked by: window_titles"
+        );
+    }
+}
@@ -101,6 +101,11 @@ pub struct Tray {
     menu_right_click_signal_handler_id: Option<glib::signal::SignalHandlerId>,
     is_idle_detector_enabled: IsIdleDetectorEnabled,
     is_paused: IsPaused,
+    postpone_duration: Duration,
+    can_postpone: bool,
+    is_break_soon: bool,
+    /// The names of the plugins that are stopping a break from starting.
+    blocking_plugins: Vec<String>,
 }
 
 fn load_pixbuf(image_bytes: &[u8]) -> gdk_pixbuf::Pixbuf {
@@ -152,6 +157,12 @@ impl Tray {
             menu_right_click_signal_handler_id,
             is_idle_detector_enabled,
             is_paused: IsPaused::No,
+            postpone_duration: Duration::from_secs(
+                config.settings.postpone_seconds.into(),
+            ),
+            can_postpone: config.settings.max_postpones_in_a_row > 0,
+            is_break_soon: false,
+            blocking_plugins: vec![],
         };
 
         tray.render_normal_icon();
@@ -252,8 +263,32 @@ impl Tray {
         self.conn_popup_menu();
     }
 
-    pub fn break_end(&self) {
+    pub fn break_end(&mut self) {
         self.render_normal_icon();
+        self.is_break_soon = false;
+        self.conn_popup_menu();
+    }
+
+    pub fn set_can_postpone(&mut self, can_postpone: bool) {
+        self.can_postpone = can_postpone;
+        self.conn_popup_menu();
+    }
+
+    pub fn set_postpone_duration(&mut self, postpone_duration: Duration) {
+        self.postpone_duration = postpone_duration;
+        self.conn_popup_menu();
+    }
+
+    /// Show a desktop notification.
+    pub fn notify(&self, summary: &str, body: &str) {
+        let res = notify_rust::Notification::new()
+            .appname("break-time")
+            .summary(summary)
+            .body(body)
+            .show();
+        if let Err(err) = res {
+            println!("Could not show notification \"{}\": {}", summary, err);
+        }
     }
 
     pub fn set_is_idle_detector_enabled(
@@ -273,6 +308,12 @@ impl Tray {
 
         let is_idle_detector_enabled = self.is_idle_detector_enabled;
         let is_paused = self.is_paused;
+        let postpone_duration = self.postpone_duration;
+        // Postponing is only shown right before a break.  Postponing a break
+        // that is further away than the postpone time would just make the
+        // break occur sooner.
+        let show_postpone =
+            self.can_postpone && self.is_break_soon && !is_paused.into_bool();
 
         let sender = self.sender.clone();
         let signal_handler_id = connect_popup_menu(
@@ -306,6 +347,21 @@ impl Tray {
                     }
                 }
 
+                if show_postpone {
+                    let postpone_item =
+                        gtk::MenuItem::new_with_label(&format!(
+                            "Postpone Break ({})",
+                            remaining_duration_to_text(postpone_duration)
+                        ));
+                    let sender_clone = sender.clone();
+                    postpone_item.connect_activate(move |_| {
+                        sender_clone
+                            .send(Msg::Postpone(postpone_duration))
+                            .expect("Could not send Msg::Postpone");
+                    });
+                    menu.append(&postpone_item);
+                }
+
                 match is_idle_detector_enabled {
                     IsIdleDetectorEnabled::No => {
                         let enable_idle_detector_item =
@@ -356,17 +412,43 @@ impl Tray {
     }
 
     fn set_time_remaining_tool_tip(&self, remaining_time: Duration) {
+        if self.blocking_plugins.is_empty() {
+            self.set_tooltip_text(&format!(
+                "break-time: {} until next break",
+                remaining_duration_to_text(remaining_time)
+            ));
+        } else {
+            self.set_blocked_tool_tip();
+        }
+    }
+
+    fn set_blocked_tool_tip(&self) {
         self.set_tooltip_text(&format!(
-            "break-time: {} until next break",
-            remaining_duration_to_text(remaining_time)
+            "break-time: break pending, blocked by: {}",
+            self.blocking_plugins.join(", ")
         ));
     }
 
-    pub fn update_time_remaining(&self, remaining_time: Duration) {
+    pub fn set_blocking_plugins(&mut self, blocking_plugins: Vec<String>) {
+        self.blocking_plugins = blocking_plugins;
+        if self.blocking_plugins.is_empty() {
+            self.set_tooltip_text("break-time");
+        } else {
+            self.set_blocked_tool_tip();
+        }
+    }
+
+    pub fn update_time_remaining(&mut self, remaining_time: Duration) {
         if remaining_time <= Duration::from_secs(5 * 60) {
             self.render_time_remaining_before_break(remaining_time);
         }
 
+        let is_break_soon = remaining_time <= self.postpone_duration;
+        if is_break_soon != self.is_break_soon {
+            self.is_break_soon = is_break_soon;
+            self.conn_popup_menu();
+        }
+
         self.set_time_remaining_tool_tip(remaining_time);
     }
 }
@@ -377,6 +459,15 @@ pub enum IsPaused {
     No,
 }
 
+impl IsPaused {
+    pub const fn into_bool(self) -> bool {
+        match self {
+            Self::Yes => true,
+            Self::No => false,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum IsIdleDetectorEnabled {
     Yes,
@@ -391,7 +482,7 @@ fn duration_to_text(duration: Duration) -> String {
     }
 }
 
-fn remaining_duration_to_text(duration: Duration) -> String {
+pub fn remaining_duration_to_text(duration: Duration) -> String {
     let duration_secs = duration.as_secs();
     if duration_secs > 60 {
         format!(
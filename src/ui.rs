@@ -7,10 +7,10 @@ mod state;
 use glib::clone;
 use glib::source::Continue;
 use gtk::Inhibit;
-use std::time::{Duration, SystemTime};
+use std::time::Duration;
 
 use super::Msg;
-use crate::config::Config;
+use crate::config::{BreakTier, Config};
 use prelude::*;
 use state::{Message, State};
 
@@ -28,24 +28,24 @@ fn handle_msg_recv(
 ) -> Continue {
     // enable(state);
 
+    for window in state.get_app_wins() {
+        window.hide();
+        window.destroy();
+    }
+
     match msg {
-        Message::End => {
-            for window in state.get_app_wins() {
-                window.hide();
-                window.destroy();
-            }
-            state.notify_app_end();
+        Message::End => state.notify_app_end(),
+        Message::Postpone => state.notify_app_postpone(),
+    }
 
-            focus_previous_window(
-                x11,
-                root_win,
-                net_active_win_atom,
-                option_old_active_win,
-            );
+    focus_previous_window(
+        x11,
+        root_win,
+        net_active_win_atom,
+        option_old_active_win,
+    );
 
-            Continue(false)
-        }
-    }
+    Continue(false)
 }
 
 fn focus_previous_window(
@@ -100,13 +100,28 @@ fn decrement_presses_remaining(state: &State) {
     }
 }
 
-fn setup(state: &State) {
+fn setup(state: &State, break_tier: &BreakTier) {
     for window in state.get_app_wins() {
         css::setup(window.upcast_ref());
     }
+
+    for label in state.get_message_labels() {
+        label.set_text(&break_tier.message);
+    }
+
+    if state.can_postpone {
+        let postpone_text = format!(
+            "Postpone break ({})",
+            crate::tray::remaining_duration_to_text(state.postpone_duration)
+        );
+        for button in state.get_postpone_buttons() {
+            button.set_label(&postpone_text);
+            button.show();
+        }
+    }
 }
 
-fn connect_events(config: &Config, state: &State) {
+fn connect_events(break_tier: &BreakTier, state: &State) {
     for window in state.get_app_wins() {
         window.connect_key_release_event(
             clone!(@strong state => move |_, event_key| {
@@ -119,9 +134,16 @@ fn connect_events(config: &Config, state: &State) {
         );
     }
 
+    if state.can_postpone {
+        for button in state.get_postpone_buttons() {
+            button.connect_clicked(clone!(@strong state => move |_| {
+                state.postpone();
+            }));
+        }
+    }
+
     // the full time we want to wait for
-    let full_time =
-        Duration::new(config.settings.break_duration_seconds.into(), 0);
+    let full_time = Duration::new(break_tier.break_duration_seconds.into(), 0);
 
     gtk::timeout_add(
         200,
@@ -138,20 +160,19 @@ fn update_time_remaining(state: &State, full_time: Duration) -> Continue {
         return Continue(false);
     }
 
-    let system_time_now = SystemTime::now();
-    let option_system_time_diff =
-        system_time_now.duration_since(state.start_time).ok();
-    let option_system_time_remaining = option_system_time_diff
-        .and_then(|system_time_diff| full_time.checked_sub(system_time_diff));
+    // This uses CLOCK_BOOTTIME, so that changing the wall clock doesn't affect
+    // the length of the break, but being suspended still counts towards it.
+    let time_diff = state.start_time.elapsed();
+    let option_time_remaining = full_time.checked_sub(time_diff);
 
-    match option_system_time_remaining {
+    match option_time_remaining {
         None => {
             end_break(&state);
             Continue(false)
         }
-        Some(system_time_remaining) => {
+        Some(time_remaining) => {
             for label in state.get_time_remaining_labels() {
-                let total_secs_remaining = system_time_remaining.as_secs();
+                let total_secs_remaining = time_remaining.as_secs();
                 let mins: u64 = total_secs_remaining / 60;
                 let secs: u64 = total_secs_remaining % 60;
                 label.set_text(&format!("{:02}:{:02}", mins, secs));
@@ -238,7 +259,12 @@ fn setup_windows(state: &State) {
     }
 }
 
-pub fn start_break(config: &Config, app_sender: glib::Sender<Msg>) {
+pub fn start_break(
+    config: &Config,
+    break_tier: &BreakTier,
+    app_sender: glib::Sender<Msg>,
+    can_postpone: bool,
+) {
     let x11 = X11::connect();
 
     let net_active_win_atom = x11.create_atom("_NET_ACTIVE_WINDOW").expect(
@@ -254,11 +280,12 @@ pub fn start_break(config: &Config, app_sender: glib::Sender<Msg>) {
     let (sender, receiver) =
         glib::MainContext::channel(glib::source::PRIORITY_DEFAULT);
 
-    let state = State::new(config, app_sender, sender);
+    let state =
+        State::new(config, break_tier, app_sender, sender, can_postpone);
 
-    setup(&state);
+    setup(&state, break_tier);
 
-    connect_events(&config, &state);
+    connect_events(break_tier, &state);
 
     redisplay(&state);
 
@@ -1,14 +1,17 @@
 use super::builder;
 use super::prelude::*;
 use std::sync::{Arc, RwLock, RwLockReadGuard};
-use std::time::SystemTime;
+use std::time::Duration;
 
-use crate::config::Config;
+use crate::clock::BootInstant;
+use crate::config::{BreakTier, Config};
+use crate::history::BreakEnd;
 use crate::Msg;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Message {
     End,
+    Postpone,
 }
 
 #[derive(Clone, Debug)]
@@ -62,15 +65,20 @@ pub struct State {
     pub monitors: Vec<Monitor>,
     pub sender: glib::Sender<Message>,
     pub presses_remaining: Arc<RwLock<u32>>,
-    pub start_time: SystemTime,
+    pub start_time: BootInstant,
     pub app_sender: glib::Sender<Msg>,
+    pub can_postpone: bool,
+    pub postpone_duration: Duration,
+    pub is_postponed: Arc<RwLock<bool>>,
 }
 
 impl State {
     pub fn new(
         config: &Config,
+        break_tier: &BreakTier,
         app_sender: glib::Sender<Msg>,
         sender: glib::Sender<Message>,
+        can_postpone: bool,
     ) -> Self {
         let monitors = Monitor::all();
         let monitors_num = monitors.len();
@@ -84,10 +92,15 @@ impl State {
             monitors,
             sender,
             presses_remaining: Arc::new(RwLock::new(
-                config.settings.clicks_to_end_break_early,
+                break_tier.clicks_to_end_break_early,
             )),
-            start_time: SystemTime::now(),
+            start_time: BootInstant::now(),
             app_sender,
+            can_postpone,
+            postpone_duration: Duration::from_secs(
+                config.settings.postpone_seconds.into(),
+            ),
+            is_postponed: Arc::new(RwLock::new(false)),
         }
     }
 
@@ -126,6 +139,13 @@ impl State {
             .collect()
     }
 
+    pub fn get_message_labels(&self) -> Vec<gtk::Label> {
+        self.builders
+            .iter()
+            .map(|builder| builder.get_object_expect("app_wi"))
+            .collect()
+    }
+
     pub fn get_time_remaining_labels(&self) -> Vec<gtk::Label> {
         self.builders
             .iter()
@@ -140,14 +160,37 @@ impl State {
             .collect()
     }
 
+    pub fn get_postpone_buttons(&self) -> Vec<gtk::Button> {
+        self.builders
+            .iter()
+            .map(|builder| builder.get_object_expect("postpone_button"))
+            .collect()
+    }
+
+    pub fn notify_app_postpone(&self) {
+        self.app_sender
+            .send(Msg::Postpone(self.postpone_duration))
+            .expect(
+            "TODO: figure out what to do about channels potentially failing",
+        );
+    }
+
     pub fn notify_app_end(&self) {
-        self.app_sender.send(Msg::EndBreak).expect(
+        // If there are no presses remaining, then the break was ended early
+        // with the spacebar.
+        let break_end = if *self.presses_remaining.read().unwrap() == 0 {
+            BreakEnd::Skipped
+        } else {
+            BreakEnd::Timeout
+        };
+        self.app_sender.send(Msg::EndBreak(break_end)).expect(
             "TODO: figure out what to do about channels potentially failing",
         );
     }
 
     pub fn has_break_ended(&self) -> bool {
         *self.presses_remaining.read().unwrap() == 0
+            || *self.is_postponed.read().unwrap()
     }
 
     pub fn end(&self) {
@@ -155,4 +198,11 @@ impl State {
             "TODO: figure out what to do about channels potentially failing",
         );
     }
+
+    pub fn postpone(&self) {
+        *self.is_postponed.write().unwrap() = true;
+        self.sender.send(Message::Postpone).expect(
+            "TODO: figure out what to do about channels potentially failing",
+        );
+    }
 }
@@ -1,6 +1,116 @@
 use crate::prelude::*;
 
 use byteorder::{LittleEndian, ReadBytesExt};
+use serde::{Deserialize, Serialize};
+
+/// The most number of 32-bit values to read from a list property, like
+/// `_NET_WM_STATE`.
+const LIST_PROP_LENGTH_TO_GET: u32 = 1024;
+
+/// The two parts of a window's `WM_CLASS` property.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WmClass {
+    /// The instance name, like `Navigator`.
+    pub instance: String,
+    /// The class, like `Firefox`.
+    pub class: String,
+}
+
+impl WmClass {
+    /// Parse the raw value of `WM_CLASS`, which is the instance name and the
+    /// class, each followed by a NUL byte.
+    pub fn parse(raw: &[u8]) -> Option<Self> {
+        let mut parts = raw.split(|b| *b == 0);
+        let instance = String::from_utf8(parts.next()?.to_vec()).ok()?;
+        let class = String::from_utf8(parts.next()?.to_vec()).ok()?;
+        Some(Self { instance, class })
+    }
+}
+
+/// Which windows a plugin looks at.
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Windows {
+    /// The direct children of the root window.  Some window managers put
+    /// application windows inside frame windows, so those are missed.
+    TopLevel,
+    /// The windows in `_NET_CLIENT_LIST`, which is every application window
+    /// the window manager knows about, including minimized ones.  This falls
+    /// back to the top-level windows if the window manager doesn't set
+    /// `_NET_CLIENT_LIST`.
+    #[serde(alias = "all")]
+    ClientList,
+    /// Only the window that has focus, from `_NET_ACTIVE_WINDOW`.
+    Active,
+    /// The windows in `_NET_CLIENT_LIST` that aren't minimized or on a
+    /// different workspace.
+    Visible,
+}
+
+/// The atoms needed to find the windows for a `Windows` setting.
+pub struct WindowFinder {
+    net_client_list_atom: xcb::Atom,
+    net_active_window_atom: xcb::Atom,
+    net_wm_state_atom: xcb::Atom,
+    net_wm_state_hidden_atom: xcb::Atom,
+}
+
+impl WindowFinder {
+    pub fn new(x11: &X11) -> Option<Self> {
+        Some(Self {
+            net_client_list_atom: x11.create_atom("_NET_CLIENT_LIST")?,
+            net_active_window_atom: x11.create_atom("_NET_ACTIVE_WINDOW")?,
+            net_wm_state_atom: x11.create_atom("_NET_WM_STATE")?,
+            net_wm_state_hidden_atom: x11
+                .create_atom("_NET_WM_STATE_HIDDEN")?,
+        })
+    }
+
+    /// Get the windows to check for `windows`.  This is `None` if the root
+    /// window can't be found.
+    pub fn get_wins(
+        &self,
+        x11: &X11,
+        windows: Windows,
+    ) -> Option<Vec<xcb::Window>> {
+        let root_win = x11.get_root_win()?;
+        let wins = match windows {
+            Windows::TopLevel => x11.get_top_level_wins(),
+            Windows::ClientList => self.get_client_list_wins(x11, root_win),
+            Windows::Active => x11
+                .get_win_prop(root_win, self.net_active_window_atom)
+                .into_iter()
+                .collect(),
+            Windows::Visible => self
+                .get_client_list_wins(x11, root_win)
+                .into_iter()
+                .filter(|win| self.is_visible(x11, *win))
+                .collect(),
+        };
+        Some(wins)
+    }
+
+    /// Get the windows managed by the window manager.  This falls back to all
+    /// the top-level windows if the window manager doesn't set
+    /// `_NET_CLIENT_LIST`.
+    fn get_client_list_wins(
+        &self,
+        x11: &X11,
+        root_win: xcb::Window,
+    ) -> Vec<xcb::Window> {
+        x11.get_win_windows(root_win, self.net_client_list_atom)
+            .unwrap_or_else(|| x11.get_top_level_wins())
+    }
+
+    /// Whether a window can be seen, so it isn't minimized or on a different
+    /// workspace.
+    fn is_visible(&self, x11: &X11, win: xcb::Window) -> bool {
+        x11.is_viewable(win)
+            && !x11
+                .get_win_atoms(win, self.net_wm_state_atom)
+                .contains(&self.net_wm_state_hidden_atom)
+    }
+}
 
 pub struct X11 {
     pub conn: xcb::Connection,
@@ -73,4 +183,100 @@ impl X11 {
             Some(window)
         }
     }
+
+    /// Get the value of a property that is a list of atoms, like
+    /// `_NET_WM_STATE`.  This is empty if the property isn't set.
+    pub fn get_win_atoms(
+        &self,
+        win: xcb::Window,
+        atom: xcb::Atom,
+    ) -> Vec<xcb::Atom> {
+        self.get_win_list_prop(win, atom, xcb::ATOM_ATOM)
+    }
+
+    /// Get the value of a property that is a list of windows, like
+    /// `_NET_CLIENT_LIST`.  This is `None` if the property isn't set.
+    pub fn get_win_windows(
+        &self,
+        win: xcb::Window,
+        atom: xcb::Atom,
+    ) -> Option<Vec<xcb::Window>> {
+        let windows = self.get_win_list_prop(win, atom, xcb::ATOM_WINDOW);
+        if windows.is_empty() {
+            None
+        } else {
+            Some(windows)
+        }
+    }
+
+    /// Whether a window is mapped, and all of its parents are too.
+    pub fn is_viewable(&self, win: xcb::Window) -> bool {
+        xcb::get_window_attributes(&self.conn, win)
+            .get_reply()
+            .map_or(false, |reply| {
+                u32::from(reply.map_state()) == xcb::MAP_STATE_VIEWABLE
+            })
+    }
+
+    fn get_win_list_prop(
+        &self,
+        win: xcb::Window,
+        atom: xcb::Atom,
+        prop_type: xcb::Atom,
+    ) -> Vec<u32> {
+        xcb::get_property(
+            &self.conn,
+            false,
+            win,
+            atom,
+            prop_type,
+            0,
+            LIST_PROP_LENGTH_TO_GET,
+        )
+        .get_reply()
+        .ok()
+        .filter(|reply| reply.format() == 32)
+        .map_or_else(Vec::new, |reply| reply.value::<u32>().to_vec())
+    }
+
+    pub fn get_wm_class(&self, win: xcb::Window) -> Option<WmClass> {
+        let reply = xcb::get_property(
+            &self.conn,
+            false,
+            win,
+            xcb::ATOM_WM_CLASS,
+            xcb::ATOM_STRING,
+            0,
+            LIST_PROP_LENGTH_TO_GET,
+        )
+        .get_reply()
+        .ok()?;
+        WmClass::parse(reply.value::<u8>())
+    }
+
+    /// Get all the direct children of the root window.
+    pub fn get_top_level_wins(&self) -> Vec<xcb::Window> {
+        self.get_root_win()
+            .and_then(|root_win| {
+                xcb::query_tree(&self.conn, root_win).get_reply().ok()
+            })
+            .map_or_else(Vec::new, |reply| reply.children().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_wm_class() {
+        assert_eq!(
+            WmClass::parse(b"Navigator\0Firefox\0"),
+            Some(WmClass {
+                instance: String::from("Navigator"),
+                class: String::from("Firefox"),
+            })
+        );
+        assert_eq!(WmClass::parse(b""), None);
+    }
 }